@@ -0,0 +1,3 @@
+DROP INDEX idx_publications_build_dir;
+
+DROP TABLE publications;
\ No newline at end of file
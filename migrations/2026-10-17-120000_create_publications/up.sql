@@ -0,0 +1,20 @@
+CREATE TABLE publications (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  revision_id INTEGER NOT NULL,
+
+  build_dir TEXT NOT NULL,
+  base_url TEXT NOT NULL,
+  atomic BOOLEAN NOT NULL DEFAULT false,
+  prune BOOLEAN NOT NULL DEFAULT false,
+  include_drafts BOOLEAN NOT NULL DEFAULT false,
+  staging_dir TEXT,
+  rollback_of INTEGER,
+  redirects TEXT NOT NULL DEFAULT 'html',
+
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+  FOREIGN KEY(revision_id) REFERENCES revisions(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  FOREIGN KEY(rollback_of) REFERENCES publications(id) ON UPDATE CASCADE ON DELETE SET NULL
+);
+
+CREATE INDEX idx_publications_build_dir ON publications(build_dir);
\ No newline at end of file
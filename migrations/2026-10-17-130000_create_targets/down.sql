@@ -0,0 +1 @@
+DROP TABLE targets;
\ No newline at end of file
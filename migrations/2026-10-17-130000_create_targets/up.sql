@@ -0,0 +1,11 @@
+CREATE TABLE targets (
+  name TEXT NOT NULL PRIMARY KEY,
+
+  base_url TEXT NOT NULL,
+  build_dir TEXT NOT NULL,
+  atomic BOOLEAN NOT NULL DEFAULT false,
+  prune BOOLEAN NOT NULL DEFAULT false,
+  keep INTEGER NOT NULL DEFAULT 3,
+
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
\ No newline at end of file
@@ -0,0 +1,3 @@
+DROP INDEX idx_file_stats_input_file_id;
+
+DROP TABLE file_stats;
\ No newline at end of file
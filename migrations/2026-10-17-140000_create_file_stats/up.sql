@@ -0,0 +1,17 @@
+CREATE TABLE file_stats (
+  disk_path TEXT NOT NULL PRIMARY KEY,
+
+  size INTEGER NOT NULL,
+  -- nanoseconds since the Unix epoch
+  modified INTEGER NOT NULL,
+  inode INTEGER,
+
+  input_file_id TEXT NOT NULL,
+
+  -- nanoseconds since the Unix epoch when the file was hashed
+  recorded_at INTEGER NOT NULL,
+
+  FOREIGN KEY(input_file_id) REFERENCES input_files(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX idx_file_stats_input_file_id ON file_stats(input_file_id);
\ No newline at end of file
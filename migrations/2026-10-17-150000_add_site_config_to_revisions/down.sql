@@ -0,0 +1 @@
+ALTER TABLE revisions DROP COLUMN site_config;
\ No newline at end of file
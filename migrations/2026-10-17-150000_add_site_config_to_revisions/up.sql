@@ -0,0 +1 @@
+ALTER TABLE revisions ADD COLUMN site_config TEXT;
\ No newline at end of file
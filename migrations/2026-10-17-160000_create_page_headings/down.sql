@@ -0,0 +1,3 @@
+DROP INDEX idx_page_headings_input_file_id;
+
+DROP TABLE page_headings;
\ No newline at end of file
@@ -0,0 +1,16 @@
+CREATE TABLE page_headings (
+  revision_id INTEGER NOT NULL,
+  input_file_id TEXT NOT NULL CHECK(length(input_file_id) < 512),
+  position INTEGER NOT NULL,
+
+  level INTEGER NOT NULL,
+  anchor TEXT NOT NULL,
+  title TEXT NOT NULL,
+
+  PRIMARY KEY(revision_id, input_file_id, position),
+
+  FOREIGN KEY(revision_id) REFERENCES revisions(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  FOREIGN KEY(input_file_id) REFERENCES input_files(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX idx_page_headings_input_file_id ON page_headings(input_file_id);
\ No newline at end of file
@@ -1,10 +1,12 @@
 use core::fmt;
 use std::{
-    fs::File,
+    collections::HashMap,
+    fs::{self, File},
     io,
     ops::Deref,
     path::{Path, PathBuf},
     sync::mpsc::{self},
+    time::UNIX_EPOCH,
 };
 
 use blake3::Hash;
@@ -31,16 +33,36 @@ impl Deref for EmptyContents {
 
 pub type Contents = Box<dyn Deref<Target = [u8]> + Send + Sync>;
 
+/// Where an asset's contents come from.
+#[derive(Debug)]
+pub enum Source {
+    /// A file in the source directory.
+    Disk(PathBuf),
+    /// Contents generated from the site configuration.
+    Generated,
+}
+
 #[derive(Debug)]
 pub struct Metadata {
-    pub disk_path: PathBuf,
+    pub source: Source,
     pub logical_path: String,
     pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub modified: Option<i64>,
+    pub inode: Option<i64>,
 }
 
 impl Metadata {
+    /// Returns the path of the file in the source directory if it is not generated.
+    pub fn disk_path(&self) -> Option<&Path> {
+        match &self.source {
+            Source::Disk(disk_path) => Some(disk_path),
+            Source::Generated => None,
+        }
+    }
+
     pub fn is_inline(&self) -> bool {
-        self.disk_path
+        Path::new(&self.logical_path)
             .extension()
             .map(|ext| INLINE_CONTENT.contains(&ext.to_string_lossy().to_lowercase().as_ref()))
             .unwrap_or_default()
@@ -50,7 +72,13 @@ impl Metadata {
         if self.size == 0 {
             Ok(Box::new(EmptyContents {}))
         } else {
-            let file = File::open(&self.disk_path)?;
+            let Source::Disk(disk_path) = &self.source else {
+                return Err(io::Error::other(format!(
+                    "generated asset {} has no file",
+                    self.logical_path
+                )));
+            };
+            let file = File::open(disk_path)?;
             let mm = unsafe { Mmap::map(&file)? };
             Ok(Box::new(mm))
         }
@@ -59,8 +87,56 @@ impl Metadata {
 
 pub struct Asset {
     pub meta: Metadata,
-    pub contents: Contents,
+    /// The file contents or `None` if the file is unchanged since it was last hashed.
+    pub contents: Option<Contents>,
+    pub hash: Hash,
+}
+
+/// Files modified this close to when they were hashed may have been modified
+/// again without a change to the modification time.
+const RACY_WINDOW_NANOS: i64 = 2_000_000_000;
+
+/// A previously hashed file.
+#[derive(Debug)]
+pub struct Known {
+    pub logical_path: String,
+    pub size: u64,
+    pub modified: i64,
+    pub inode: Option<i64>,
+    /// Time in nanoseconds since the Unix epoch when the file was hashed.
+    pub recorded_at: i64,
     pub hash: Hash,
+    /// Path in the cache directory if the contents are not stored in the database.
+    pub cache_path: Option<PathBuf>,
+}
+
+impl Known {
+    /// Returns true if the file can be assumed to be unchanged without reading it.
+    ///
+    /// If the metadata is ambiguous, the file should be hashed again.
+    fn is_unchanged(&self, meta: &Metadata) -> bool {
+        meta.logical_path == self.logical_path
+            && meta.size == self.size
+            && meta.modified == Some(self.modified)
+            && meta.inode == self.inode
+            && self.modified < self.recorded_at - RACY_WINDOW_NANOS
+            && self.cache_path.as_ref().is_none_or(|path| path.exists())
+    }
+}
+
+/// Previously hashed files by disk path.
+pub type KnownFiles = HashMap<PathBuf, Known>;
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> Option<i64> {
+    use std::os::unix::fs::MetadataExt;
+
+    i64::try_from(metadata.ino()).ok()
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> Option<i64> {
+    None
 }
 
 impl fmt::Debug for Asset {
@@ -90,26 +166,33 @@ where
                 .strip_prefix(base_path)
                 .expect("disk path should have been able to strip prefix base_path")
                 .to_str()
-                .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "not a valid UTF-8 path"))?
+                .ok_or_else(|| io::Error::other("not a valid UTF-8 path"))?
                 .to_string();
             let metadata = disk_path.metadata()?;
             let size = metadata.len();
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .and_then(|duration| i64::try_from(duration.as_nanos()).ok());
 
             f(Metadata {
-                disk_path,
+                source: Source::Disk(disk_path),
                 logical_path,
                 size,
+                modified,
+                inode: inode(&metadata),
             })?;
         }
 
         Ok::<_, io::Error>(())
     })
-    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+    .map_err(io::Error::other)??;
 
     Ok(())
 }
 
-const SRC_SUB_DIRS: &[&str] = &["assets", "content", "static", "templates"];
+pub const SRC_SUB_DIRS: &[&str] = &["assets", "content", "static", "templates"];
 
 fn walk_src_dirs<F>(src: &Path, mut f: F) -> io::Result<()>
 where
@@ -117,13 +200,38 @@ where
 {
     for &prefix in SRC_SUB_DIRS {
         let dir = &src.join(prefix);
-        walk_dir(dir, &mut f)?;
+        // A missing directory has no files.
+        if dir.is_dir() {
+            walk_dir(dir, &mut f)?;
+        }
     }
 
     Ok(())
 }
 
-pub fn process(sink: &mut mpsc::Sender<Asset>, meta: Metadata) -> io::Result<()> {
+pub fn process(
+    sink: &mut mpsc::Sender<Asset>,
+    known: &KnownFiles,
+    meta: Metadata,
+) -> io::Result<()> {
+    if let Some(known) = meta
+        .disk_path()
+        .and_then(|disk_path| known.get(disk_path))
+        .filter(|known| known.is_unchanged(&meta))
+    {
+        tracing::trace!("Unchanged: {}", meta.logical_path);
+
+        let hash = known.hash;
+        sink.send(Asset {
+            meta,
+            contents: None,
+            hash,
+        })
+        .map_err(io::Error::other)?;
+
+        return Ok(());
+    }
+
     tracing::trace!("Processing: {}", meta.logical_path);
 
     let contents = meta.contents()?;
@@ -136,23 +244,29 @@ pub fn process(sink: &mut mpsc::Sender<Asset>, meta: Metadata) -> io::Result<()>
 
     sink.send(Asset {
         meta,
-        contents,
+        contents: Some(contents),
         hash,
     })
-    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    .map_err(io::Error::other)?;
 
     Ok(())
 }
 
-pub fn walk<F, T>(src: &Path, f: F) -> anyhow::Result<T>
+/// Walks the source directories and sends each file to `f`.
+///
+/// Files in `known` which are unchanged are not read.
+pub fn walk<F, T>(src: &Path, known: &KnownFiles, f: F) -> anyhow::Result<T>
 where
     F: FnOnce(mpsc::Receiver<Asset>) -> anyhow::Result<T> + Sync + Send,
     T: Sync + Send,
 {
+    // Disk paths are absolute so they can be compared with known files.
+    let src = &src.canonicalize()?;
+
     let mut walk_result = Ok(());
     let mut process_result = Err(anyhow::anyhow!(""));
 
-    rayon::scope(|s| {
+    let send_result = rayon::scope(|s| {
         let (tx, rx) = mpsc::channel();
 
         let walk_result = &mut walk_result;
@@ -165,21 +279,92 @@ where
         });
 
         s.spawn(move |_| {
-            *walk_result = walk_src_dirs(src, |metadata| {
-                tx.send(metadata)
-                    .expect("metadata should always be sent to receiver");
-                Ok(())
-            });
+            // Processing stops at the first error.
+            *walk_result =
+                walk_src_dirs(src, |metadata| tx.send(metadata).map_err(io::Error::other));
         });
 
         rx.into_iter()
             .par_bridge()
-            .map_with(event_tx, process)
-            .collect::<Result<_, _>>()
-    })?;
+            .map_with(event_tx, |sink, meta| process(sink, known, meta))
+            .collect::<Result<(), _>>()
+    });
 
-    walk_result?;
+    // Sending fails if `f` stopped receiving because of an error, so its error
+    // is returned first.
     let res = process_result?;
+    send_result?;
+    walk_result?;
 
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: i64 = 1_000_000_000;
+
+    fn meta(size: u64, modified: i64) -> Metadata {
+        Metadata {
+            source: Source::Disk(PathBuf::from("src/static/a.txt")),
+            logical_path: "static/a.txt".to_string(),
+            size,
+            modified: Some(modified),
+            inode: Some(1),
+        }
+    }
+
+    fn known(modified: i64, recorded_at: i64) -> Known {
+        Known {
+            logical_path: "static/a.txt".to_string(),
+            size: 3,
+            modified,
+            inode: Some(1),
+            recorded_at,
+            hash: blake3::hash(b"abc"),
+            cache_path: None,
+        }
+    }
+
+    #[test]
+    fn unchanged_metadata() {
+        let known = known(100 * SECOND, 110 * SECOND);
+        assert!(known.is_unchanged(&meta(3, 100 * SECOND)));
+
+        // Same size with a different modification time.
+        assert!(!known.is_unchanged(&meta(3, 101 * SECOND)));
+        assert!(!known.is_unchanged(&meta(4, 100 * SECOND)));
+        assert!(!known.is_unchanged(&Metadata {
+            inode: Some(2),
+            ..meta(3, 100 * SECOND)
+        }));
+        assert!(!known.is_unchanged(&Metadata {
+            modified: None,
+            ..meta(3, 100 * SECOND)
+        }));
+    }
+
+    #[test]
+    fn modified_in_racy_window() {
+        // The file may have been modified again in the same timestamp granularity.
+        let known = known(100 * SECOND, 100 * SECOND + RACY_WINDOW_NANOS);
+        assert!(!known.is_unchanged(&meta(3, 100 * SECOND)));
+
+        let known = Known {
+            recorded_at: 100 * SECOND + RACY_WINDOW_NANOS + 1,
+            ..known
+        };
+        assert!(known.is_unchanged(&meta(3, 100 * SECOND)));
+    }
+
+    #[test]
+    fn missing_cache_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let known = Known {
+            cache_path: Some(dir.path().join("missing")),
+            ..known(100 * SECOND, 110 * SECOND)
+        };
+        assert!(!known.is_unchanged(&meta(3, 100 * SECOND)));
+    }
+}
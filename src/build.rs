@@ -2,24 +2,35 @@
 //!
 //! Collect the local file information and builds the metadata.
 
-use std::{fs, path::Path, sync::mpsc};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use diesel::Connection;
+use diesel::{prelude::*, Connection};
 use itertools::Itertools;
 use lightningcss::stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet};
 use toml_edit::Document;
 
 use crate::{
-    asset::{Asset, Contents},
-    content,
+    asset::{self, Asset, Contents, Known, KnownFiles},
+    content, highlight, markdown,
     models::{
-        input_file::{self, NewInputFile, Ty},
-        page::NewPage,
+        file_stat::{FileStat, NewFileStat},
+        input_file::{self, InputFile, NewInputFile, Ty},
+        page::{NewPage, Page},
+        page_alias::NewPageAlias,
+        page_heading::NewPageHeading,
+        page_tag::NewPageTag,
         revision::Revision,
         revision_file::NewRevisionFile,
-        route::NewRoute,
-        DbConn,
+        route::{NewRoute, Route},
+        DbConn, DbPool,
     },
+    site, taxonomy,
 };
 
 fn preprocess_stylesheet(contents: &Contents) -> anyhow::Result<Contents> {
@@ -32,51 +43,137 @@ fn preprocess_stylesheet(contents: &Contents) -> anyhow::Result<Contents> {
     Ok(Box::new(output.code.as_bytes().to_vec()))
 }
 
+/// Returns the strings in a front matter array or an empty list if the key is missing.
+fn string_array<'a>(
+    doc: &'a Document,
+    key: &str,
+    logical_path: &str,
+) -> anyhow::Result<Vec<&'a str>> {
+    let Some(item) = doc.get(key) else {
+        return Ok(Vec::new());
+    };
+
+    item.as_array()
+        .and_then(|array| array.iter().map(toml_edit::Value::as_str).collect())
+        .ok_or_else(|| anyhow::anyhow!("{key} in {logical_path} must be an array of strings"))
+}
+
+/// Stores the headings of a page's content in the revision.
+///
+/// Headings are found with the Markdown extensions of the revision's site
+/// configuration, so they are found again even if the page is unchanged.
+fn create_headings(
+    rev: &Revision,
+    input_file_id: &str,
+    logical_path: &str,
+    site_extensions: markdown::Extensions,
+    conn: &mut DbConn,
+) -> anyhow::Result<()> {
+    let page = Page::by_input_file_id(input_file_id).get_result(conn)?;
+    let Some(contents) = InputFile::by_id(input_file_id).get_result(conn)?.contents else {
+        anyhow::bail!("content {logical_path} is not stored");
+    };
+    let (_, contents) = contents.split_at(usize::try_from(page.offset)?);
+
+    let extensions = page
+        .front_matter
+        .as_deref()
+        .map(|front_matter| markdown::Extensions::from_front_matter(front_matter, logical_path))
+        .transpose()?
+        .unwrap_or_default()
+        .or(site_extensions);
+
+    for (position, heading) in markdown::render(core::str::from_utf8(contents)?, &extensions, None)?
+        .headings
+        .iter()
+        .enumerate()
+    {
+        NewPageHeading {
+            revision_id: rev.id,
+            input_file_id,
+            position: i64::try_from(position)?,
+            level: i64::try_from(heading.level)?,
+            anchor: &heading.anchor,
+            title: &heading.title,
+        }
+        .create(conn)?;
+    }
+
+    Ok(())
+}
+
 #[allow(clippy::too_many_lines)]
 pub fn create_revision(
     cache_dir: &Path,
+    started_at: i64,
+    site_config: Option<&str>,
     evt_rx: &mpsc::Receiver<Asset>,
+    generated: Vec<Asset>,
     conn: &mut DbConn,
 ) -> anyhow::Result<Revision> {
     conn.transaction(|conn| {
-        let rev = Revision::create(conn)?;
+        let rev = Revision::create(site_config, conn)?;
+        let config = site_config
+            .map(site::Config::parse)
+            .transpose()?
+            .unwrap_or_default();
+        let mut templates = BTreeMap::new();
 
         // TODO: Should receive a "Done" event to commit the transaction
-        while let Ok(mut asset) = evt_rx.recv() {
+        for mut asset in evt_rx.iter().chain(generated) {
             let content_hash_string = format!("{:x}", asset.hash.as_bytes().iter().format(""));
             let input_file_id = format!("{content_hash_string},{}", asset.meta.logical_path);
 
             let is_inline = asset.meta.is_inline();
             let ty = input_file::ty(&asset.meta.logical_path);
 
-            // Pre-process content such as minification which would always done per fetch/publish regardless of user.
-            if ty.is_stylesheet() {
-                asset.contents = preprocess_stylesheet(&asset.contents)?;
-            }
+            // Unchanged files already have an input file and any cached contents.
+            let created_input_file = if let Some(contents) = &mut asset.contents {
+                // Pre-process content such as minification which would always done per fetch/publish regardless of user.
+                if ty.is_stylesheet() {
+                    *contents = preprocess_stylesheet(contents)?;
+                }
+
+                let created_input_file = NewInputFile::new(
+                    &input_file_id,
+                    &asset.meta.logical_path,
+                    asset.hash.as_bytes().as_slice(),
+                    is_inline.then_some(&*contents),
+                )
+                .create(conn)?;
 
-            let created_input_file = NewInputFile::new(
-                &input_file_id,
-                &asset.meta.logical_path,
-                asset.hash.as_bytes().as_slice(),
-                is_inline.then_some(&asset.contents),
-            )
-            .create(conn)?;
-
-            if !is_inline {
-                let cache_path = cache_dir.join(&content_hash_string);
-                if !cache_path.exists() {
-                    tracing::trace!(
-                        "Copying file {} to {}",
-                        asset.meta.disk_path.display(),
-                        cache_path.display()
-                    );
-                    fs::write(&cache_path, &**asset.contents)?;
+                if !is_inline {
+                    let cache_path = cache_dir.join(&content_hash_string);
+                    if !cache_path.exists() {
+                        tracing::trace!(
+                            "Copying file {} to {}",
+                            asset.meta.logical_path,
+                            cache_path.display()
+                        );
+                        fs::write(&cache_path, &***contents)?;
+                    }
+                    debug_assert_eq!(contents.len() as u64, cache_path.metadata().unwrap().len());
+                }
+
+                if let (Some(disk_path), Some(modified)) = (
+                    asset.meta.disk_path().and_then(Path::to_str),
+                    asset.meta.modified,
+                ) {
+                    NewFileStat {
+                        disk_path,
+                        size: i64::try_from(asset.meta.size)?,
+                        modified,
+                        inode: asset.meta.inode,
+                        input_file_id: &input_file_id,
+                        recorded_at: started_at,
+                    }
+                    .upsert(conn)?;
                 }
-                debug_assert_eq!(
-                    asset.contents.len() as u64,
-                    cache_path.metadata().unwrap().len()
-                );
-            }
+
+                created_input_file
+            } else {
+                false
+            };
 
             NewRevisionFile::new(rev.id, &input_file_id).create(conn)?;
 
@@ -110,15 +207,29 @@ pub fn create_revision(
                     NewRoute::new(rev.id, &path, &input_file_id).create(conn)?;
                 }
                 Ty::Content(path) => {
-                    if let Some(path) = path.strip_suffix(".md") {
-                        let path = format!("{path}.html");
+                    if let Some(path) = config.routes.content(path) {
                         tracing::trace!("Adding content route: {}", path);
+                        if let Some(route) = Route::by_revision_id_and_route(rev.id, &path)
+                            .first(conn)
+                            .optional()?
+                        {
+                            let (_, other) =
+                                route.input_file_id.split_once(',').unwrap_or_default();
+                            anyhow::bail!(
+                                "{other} and {} are both published at {path}",
+                                asset.meta.logical_path
+                            );
+                        }
                         NewRoute::new(rev.id, &path, &input_file_id).create(conn)?;
 
-                        let contents = core::str::from_utf8(&asset.contents)?;
-                        let (front_matter, content_offset, _) = content::parse(contents)?;
-
                         if created_input_file {
+                            let contents = asset
+                                .contents
+                                .as_ref()
+                                .map_or(&[][..], |contents| &***contents);
+                            let contents = core::str::from_utf8(contents)?;
+                            let (front_matter, content_offset, _) = content::parse(contents)?;
+
                             let mut page = NewPage {
                                 input_file_id: &input_file_id,
                                 front_matter,
@@ -161,23 +272,294 @@ pub fn create_revision(
                                 page.title = doc.get("title").and_then(toml_edit::Item::as_str);
 
                                 page.create(conn)?;
+
+                                for tag in string_array(&doc, "tags", &asset.meta.logical_path)? {
+                                    NewPageTag::new(&input_file_id, tag).create(conn)?;
+                                }
+                                for alias in
+                                    string_array(&doc, "aliases", &asset.meta.logical_path)?
+                                {
+                                    // Aliases are stored relative to the base URL.
+                                    let alias = alias.trim_start_matches('/');
+                                    NewPageAlias::new(&input_file_id, alias).create(conn)?;
+                                }
                             } else {
                                 page.create(conn)?;
                             }
                         }
+
+                        create_headings(
+                            &rev,
+                            &input_file_id,
+                            &asset.meta.logical_path,
+                            config.markdown,
+                            conn,
+                        )?;
                     }
                 }
                 Ty::Static(path) => {
                     tracing::trace!("Adding static route: {}", path);
                     NewRoute::new(rev.id, path, &input_file_id).create(conn)?;
                 }
-                Ty::Template(_) => {}
+                Ty::Template(name) => {
+                    templates.insert(name.to_string(), input_file_id.clone());
+                }
                 Ty::Unknown => {
                     todo!()
                 }
             }
         }
 
+        taxonomy::create_routes(&rev, &config.taxonomy, &templates, conn)?;
+
         Ok(rev)
     })
 }
+
+/// Returns the assets generated from the site configuration.
+///
+/// Files in the source directory take precedence over generated assets.
+fn generated_assets(src: &Path, site_config: Option<&str>) -> anyhow::Result<Vec<Asset>> {
+    let Some(highlight) = site_config
+        .map(site::Config::parse)
+        .transpose()?
+        .and_then(|config| config.highlight)
+    else {
+        return Ok(Vec::new());
+    };
+    let Some(stylesheet) = highlight.stylesheet()? else {
+        return Ok(Vec::new());
+    };
+
+    let logical_path = format!("assets/{}", highlight::STYLESHEET);
+    if src.join(&logical_path).exists() {
+        tracing::debug!("Using {logical_path} instead of the generated stylesheet");
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![Asset {
+        meta: asset::Metadata {
+            source: asset::Source::Generated,
+            logical_path,
+            size: u64::try_from(stylesheet.len())?,
+            modified: None,
+            inode: None,
+        },
+        hash: blake3::hash(stylesheet.as_bytes()),
+        contents: Some(Box::new(stylesheet.into_bytes())),
+    }])
+}
+
+/// Loads the previously hashed files which can be skipped if unchanged.
+fn known_files(cache_dir: &Path, conn: &mut DbConn) -> anyhow::Result<KnownFiles> {
+    let mut known = KnownFiles::new();
+    for (stat, input_file, is_inline) in FileStat::with_input_files(conn)? {
+        let hash = blake3::Hash::from_bytes(
+            <[u8; 32]>::try_from(input_file.contents_hash)
+                .map_err(|_| anyhow::anyhow!("invalid contents hash for {}", input_file.id))?,
+        );
+        let cache_path = (!is_inline)
+            .then(|| cache_dir.join(format!("{:x}", hash.as_bytes().iter().format(""))));
+
+        known.insert(
+            PathBuf::from(stat.disk_path),
+            Known {
+                logical_path: input_file.logical_path,
+                size: u64::try_from(stat.size)?,
+                modified: stat.modified,
+                inode: stat.inode,
+                recorded_at: stat.recorded_at,
+                hash,
+                cache_path,
+            },
+        );
+    }
+
+    Ok(known)
+}
+
+/// Scans the source directory and creates a revision.
+///
+/// Unless `full` is set, files which are unchanged since they were last
+/// hashed are not read again.
+pub fn create(src: &Path, cache_dir: &Path, full: bool, pool: &DbPool) -> anyhow::Result<Revision> {
+    let started_at = i64::try_from(SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos())?;
+
+    let site_config = site::read(src)?;
+    let generated = generated_assets(src, site_config.as_deref())?;
+
+    let known = if full {
+        KnownFiles::new()
+    } else {
+        let mut conn = pool.get()?;
+        known_files(cache_dir, &mut conn)?
+    };
+
+    asset::walk(src, &known, |evt_rx| {
+        let mut conn = pool.get()?;
+        create_revision(
+            cache_dir,
+            started_at,
+            site_config.as_deref(),
+            &evt_rx,
+            generated,
+            &mut conn,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, time::Duration};
+
+    use super::*;
+    use crate::{
+        models::{page_alias::PageAlias, page_heading::PageHeading, page_tag::PageTag},
+        testing::{in_thread_pool, Site},
+    };
+
+    #[test]
+    fn full_rehashes_unchanged_files() {
+        let site = Site::new();
+        let path = site.src().join("static/a.txt");
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let write = |contents: &str| {
+            site.write("static/a.txt", contents);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        let contents = |rev: &Revision| {
+            let input_file = InputFile::find(rev, "static/a.txt", &mut site.conn())
+                .unwrap()
+                .unwrap();
+            fs::read(site.cache_dir().join(input_file.cache_file_name().unwrap())).unwrap()
+        };
+
+        write("aaa");
+        let first = site.create();
+        assert_eq!(contents(&first), b"aaa");
+
+        // A change which keeps the size and modification time is not noticed...
+        write("bbb");
+        let second = site.create();
+        assert_eq!(contents(&second), b"aaa");
+
+        // ...unless every file is hashed again.
+        let third =
+            in_thread_pool(|| create(&site.src(), &site.cache_dir(), true, &site.pool)).unwrap();
+        assert_eq!(contents(&third), b"bbb");
+    }
+
+    #[test]
+    fn tags_and_aliases() {
+        let site = Site::new();
+        site.write(
+            "content/a.md",
+            "+++\ntitle = \"A\"\ntags = [\"rust\", \"web\", \"rust\"]\naliases = [\"/old/a/\", \"a.php\"]\n+++\nA\n",
+        );
+        site.write(
+            "content/b.md",
+            "+++\ntitle = \"B\"\ntags = [\"rust\"]\n+++\nB\n",
+        );
+        let first = site.create();
+        let mut conn = site.conn();
+
+        let tags = PageTag::with_revision(&first, &mut conn).unwrap();
+        assert_eq!(
+            tags.iter().map(|tag| tag.tag.as_str()).collect::<Vec<_>>(),
+            vec!["rust", "rust", "web"]
+        );
+        let pages = PageTag::pages(&first, "rust", &mut conn).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(PageTag::pages(&first, "web", &mut conn).unwrap().len(), 1);
+
+        let aliases = PageAlias::with_revision(&first, &mut conn).unwrap();
+        assert_eq!(
+            aliases
+                .iter()
+                .map(|alias| alias.alias.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a.php", "old/a/"]
+        );
+        assert!(PageAlias::find(&first, "old/a/", &mut conn)
+            .unwrap()
+            .is_some());
+        assert!(PageAlias::find(&first, "/old/a/", &mut conn)
+            .unwrap()
+            .is_none());
+
+        // Tags and aliases of removed pages are not in later revisions.
+        fs::remove_file(site.src().join("content/a.md")).unwrap();
+        let second = site.create();
+        assert_eq!(PageTag::pages(&second, "web", &mut conn).unwrap().len(), 0);
+        assert!(PageAlias::with_revision(&second, &mut conn)
+            .unwrap()
+            .is_empty());
+        assert_eq!(PageTag::pages(&first, "web", &mut conn).unwrap().len(), 1);
+
+        site.write(
+            "content/c.md",
+            "+++\ntitle = \"C\"\ntags = \"rust\"\n+++\nC\n",
+        );
+        assert!(
+            in_thread_pool(|| create(&site.src(), &site.cache_dir(), false, &site.pool)).is_err()
+        );
+    }
+
+    #[test]
+    fn headings_of_each_revision() {
+        let site = Site::new();
+        site.write("content/index.md", "# Intro {#start}\n\nHome\n");
+        let first = site.create();
+        site.write("site.toml", "[markdown]\nheading_attributes = true\n");
+        let second = site.create();
+        let mut conn = site.conn();
+
+        let anchors = |rev: &Revision, conn: &mut DbConn| {
+            PageHeading::with_revision(rev, conn)
+                .unwrap()
+                .into_iter()
+                .map(|heading| (heading.anchor, heading.title))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            anchors(&first, &mut conn),
+            vec![("intro-start".to_string(), "Intro {#start}".to_string())]
+        );
+        assert_eq!(
+            anchors(&second, &mut conn),
+            vec![("start".to_string(), "Intro".to_string())]
+        );
+    }
+
+    #[test]
+    fn pretty_routes() {
+        let site = Site::new();
+        site.write("site.toml", "[routes]\npretty = true\n");
+        site.write("content/index.md", "Home\n");
+        site.write("content/blog/post.md", "Post\n");
+        let rev = site.create();
+
+        let routes = Route::with_revision(&rev, &mut site.conn()).unwrap();
+        assert_eq!(
+            routes
+                .iter()
+                .map(|route| route.route.as_str())
+                .collect::<Vec<_>>(),
+            vec!["blog/post/index.html", "index.html"]
+        );
+
+        site.write("content/blog/post/index.md", "Post\n");
+        let error = in_thread_pool(|| create(&site.src(), &site.cache_dir(), false, &site.pool))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.ends_with("are both published at blog/post/index.html"),
+            "{error}"
+        );
+    }
+}
@@ -1,6 +1,9 @@
-use std::{fs, path::Path};
+use std::{collections::HashSet, fs, path::Path};
+
+use itertools::Itertools;
 
 use crate::{
+    highlight,
     models::{
         input_file::{with_no_revision_file, InputFile},
         DbConn,
@@ -11,7 +14,7 @@ use crate::{
 use diesel::prelude::*;
 
 pub fn cleanup(cache_dir: &Path, conn: &mut DbConn) -> anyhow::Result<()> {
-    conn.transaction(|conn| {
+    let live = conn.transaction(|conn| {
         let files = InputFile::with_no_revision_file().load(conn)?;
 
         for (input_file, cache_file_name) in files.into_iter().flat_map(|input_file| {
@@ -29,8 +32,18 @@ pub fn cleanup(cache_dir: &Path, conn: &mut DbConn) -> anyhow::Result<()> {
 
         diesel::delete(input_files::dsl::input_files.filter(with_no_revision_file())).execute(conn)?;
 
-        Ok::<_, anyhow::Error>(())
+        let hashes = input_files::table
+            .select(input_files::contents_hash)
+            .load::<Vec<u8>>(conn)?;
+        Ok::<_, anyhow::Error>(
+            hashes
+                .iter()
+                .map(|hash| format!("{:x}", hash.iter().format("")))
+                .collect::<HashSet<_>>(),
+        )
     })?;
 
+    highlight::remove_stale(cache_dir, &live)?;
+
     Ok(())
 }
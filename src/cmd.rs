@@ -1,20 +1,29 @@
 use std::{
+    io,
     net::SocketAddr,
     path::{Path, PathBuf},
 };
 
-use clap::Subcommand;
+use chrono::{DateTime, FixedOffset, Utc};
+use clap::{Args, Subcommand, ValueEnum};
 use diesel::prelude::*;
 use tracing::info;
 use url::Url;
 
 use crate::{
-    asset, build, cleanup, delete,
+    build, cleanup, delete, dev, diff, links, list,
+    manifest::Manifest,
     models::{
+        publication::{NewPublication, Publication},
         revision::{self, Revision},
-        DbPool,
+        route::Route,
+        target::{NewTarget, Target},
+        DbConn, DbId, DbPool,
     },
-    publish,
+    prune,
+    publish::{self, RenderedRoutes},
+    redirect, serve, site, staging,
+    visibility::Visibility,
 };
 
 #[derive(Debug, Subcommand)]
@@ -23,19 +32,12 @@ pub enum Command {
     Create {
         #[arg(short, long, default_value = "./")]
         src_dir: PathBuf,
+        /// Read and hash every file even if it appears unchanged.
+        #[arg(long)]
+        full: bool,
     },
     /// Publish a revision of the site.
-    Publish {
-        /// Directory to publish the build.
-        #[arg(long, default_value = "https://127.0.0.1")]
-        base_url: Url,
-        /// Directory to publish the build.
-        #[arg(short, long, default_value = "./build")]
-        build_dir: PathBuf,
-        /// Revision to publish.
-        #[arg(short, long)]
-        revision: Option<i64>,
-    },
+    Publish(PublishArgs),
     /// Deletes a revision
     Delete {
         #[arg(short, long)]
@@ -43,45 +45,455 @@ pub enum Command {
     },
     /// Removes unreachable data in the database and cache.
     Cleanup,
+    /// Lists the revisions of the site.
+    List {
+        /// Output the revisions as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Shows the changes between two revisions.
+    Diff {
+        /// Revision to compare from.
+        #[arg(long)]
+        from: i64,
+        /// Revision to compare to.
+        #[arg(long)]
+        to: i64,
+        /// Output the changes as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Shows the history of published revisions.
+    History {
+        /// Only show publications to the named target.
+        #[arg(long, conflicts_with = "build_dir")]
+        target: Option<String>,
+        /// Only show publications to the build directory.
+        #[arg(short, long)]
+        build_dir: Option<PathBuf>,
+        /// Output the publications as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Republishes the previously published revision to a build directory.
+    #[command(group(clap::ArgGroup::new("dest").required(true)))]
+    Rollback {
+        /// Named target to roll back.
+        #[arg(long, group = "dest")]
+        target: Option<String>,
+        /// Build directory to roll back.
+        #[arg(short, long, group = "dest")]
+        build_dir: Option<PathBuf>,
+    },
+    /// Serves the site and creates a new revision whenever a source file changes.
+    Dev {
+        #[arg(short, long, default_value = "./")]
+        src_dir: PathBuf,
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+        /// Do not serve drafts and pages outside of their publish and expiry dates.
+        ///
+        /// By default every page is served.
+        #[arg(long)]
+        hide_unpublished: bool,
+    },
+    /// Serves a revision of the site from the database.
+    Serve {
+        /// Revision to serve.
+        #[arg(short, long)]
+        revision: Option<i64>,
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+        /// Do not serve drafts and pages outside of their publish and expiry dates.
+        ///
+        /// By default every page is served.
+        #[arg(long)]
+        hide_unpublished: bool,
+    },
+    /// Checks if pages were scheduled to be published or to expire since the
+    /// last publish.
+    ///
+    /// Exits with status 2 if the build directory should be republished.
+    Due {
+        /// Build directory to check.
+        #[arg(short, long, default_value = "./build")]
+        build_dir: PathBuf,
+        /// Revision to check instead of the revision last published to the build directory.
+        #[arg(short, long)]
+        revision: Option<i64>,
+        /// Start of the window instead of the time of the last publish to the build directory.
+        #[arg(long)]
+        since: Option<DateTime<FixedOffset>>,
+        /// End of the window instead of the current time.
+        #[arg(long)]
+        now: Option<DateTime<FixedOffset>>,
+        /// Consider draft pages as published.
+        ///
+        /// Drafts are also considered published if they were included in the
+        /// last publish to the build directory.
+        #[arg(long)]
+        include_drafts: bool,
+    },
+    /// Checks the internal links of a revision.
+    ///
+    /// Prints the broken links grouped by route and exits with status 1 if
+    /// there are any.
+    CheckLinks {
+        /// Revision to check instead of the most recently created revision.
+        #[arg(short, long)]
+        revision: Option<i64>,
+        /// Base URL of the published site.
+        ///
+        /// Defaults to `base_url` in the revision's site configuration or `https://127.0.0.1/`.
+        #[arg(long)]
+        base_url: Option<Url>,
+        /// Consider draft pages as published.
+        #[arg(long)]
+        include_drafts: bool,
+        /// Time to compare page publish and expiry dates against instead of the current time.
+        #[arg(long, visible_alias = "at")]
+        now: Option<DateTime<FixedOffset>>,
+    },
+    /// Manages named publish targets.
+    Target {
+        #[command(subcommand)]
+        command: TargetCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TargetCommand {
+    /// Adds a named target.
+    Add {
+        name: String,
+        /// Base URL of the published site.
+        #[arg(long)]
+        base_url: Url,
+        /// Directory to publish the build.
+        #[arg(short, long)]
+        build_dir: PathBuf,
+        /// Publish atomically. See `publish --atomic`.
+        #[arg(long)]
+        atomic: bool,
+        /// Remove stale files when publishing. See `publish --prune`.
+        #[arg(long, conflicts_with = "atomic")]
+        prune: bool,
+        /// Number of published directories to keep with `--atomic`.
+        #[arg(long, default_value_t = DEFAULT_KEEP, requires = "atomic")]
+        keep: usize,
+    },
+    /// Lists the named targets.
+    List {
+        /// Output the targets as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Removes a named target.
+    Remove { name: String },
 }
 
-pub fn create(src: &Path, cache_dir: &Path, pool: DbPool) -> anyhow::Result<()> {
+const DEFAULT_KEEP: usize = 3;
+
+const DEFAULT_BASE_URL: &str = "https://127.0.0.1";
+
+#[derive(Clone, Debug, Args)]
+pub struct PublishArgs {
+    /// Base URL of the published site.
+    ///
+    /// Defaults to `base_url` in the revision's site configuration or `https://127.0.0.1/`.
+    #[arg(long)]
+    base_url: Option<Url>,
+    /// Directory to publish the build.
+    #[arg(short, long, default_value = "./build")]
+    build_dir: PathBuf,
+    /// Revision to publish.
+    #[arg(short, long)]
+    revision: Option<i64>,
+    /// Write every route even if unchanged since the last publish.
+    #[arg(long)]
+    full: bool,
+    /// Remove files in the build directory which are not routes in the revision.
+    #[arg(long, conflicts_with = "atomic")]
+    prune: bool,
+    /// List the files which would be pruned without publishing.
+    #[arg(long, requires = "prune")]
+    dry_run: bool,
+    /// Publish into a new directory in the build directory and then atomically
+    /// switch the `current` symlink to it.
+    #[arg(long)]
+    atomic: bool,
+    /// Number of published directories to keep with `--atomic`.
+    #[arg(long, default_value_t = DEFAULT_KEEP, requires = "atomic")]
+    keep: usize,
+    /// Named target to publish to instead of the base URL and build directory options.
+    #[arg(
+        long,
+        conflicts_with_all = ["base_url", "build_dir", "prune", "atomic", "keep"]
+    )]
+    target: Option<String>,
+    /// Publish the revision currently published to another named target.
+    #[arg(long, requires = "target", conflicts_with = "revision")]
+    promote_from: Option<String>,
+    /// How to publish redirects for page aliases.
+    ///
+    /// Defaults to `redirects` in the revision's site configuration or `html`.
+    #[arg(long, value_enum)]
+    redirects: Option<redirect::Style>,
+    /// Publish draft pages.
+    #[arg(long)]
+    include_drafts: bool,
+    /// Time to compare page publish and expiry dates against instead of the current time.
+    #[arg(long, visible_alias = "at")]
+    now: Option<DateTime<FixedOffset>>,
+    /// Check the internal links before publishing and print the broken links.
+    ///
+    /// With `error`, nothing is published if a link is broken.
+    #[arg(long, value_enum)]
+    check_links: Option<links::Mode>,
+    /// The publication republished by a rollback.
+    #[arg(skip)]
+    rollback_of: Option<DbId>,
+}
+
+/// Returns the visibility of pages at `now` or the current time.
+fn visibility(include_drafts: bool, now: Option<DateTime<FixedOffset>>) -> Visibility {
+    Visibility {
+        include_drafts,
+        now: now.map_or_else(|| Utc::now().naive_utc(), |now| now.naive_utc()),
+    }
+}
+
+impl PublishArgs {
+    /// Replaces the options with the named target's configuration.
+    fn with_target(&self, conn: &mut DbConn) -> anyhow::Result<Self> {
+        let Some(name) = &self.target else {
+            return Ok(self.clone());
+        };
+
+        let target = Target::by_name(name)
+            .get_result(conn)
+            .optional()?
+            .ok_or_else(|| anyhow::anyhow!("target {name} does not exist"))?;
+
+        let revision = if let Some(from) = &self.promote_from {
+            let from_target = Target::by_name(from)
+                .get_result(conn)
+                .optional()?
+                .ok_or_else(|| anyhow::anyhow!("target {from} does not exist"))?;
+            let publication =
+                Publication::by_build_dir(&target_name(Path::new(&from_target.build_dir))?)
+                    .first(conn)
+                    .optional()?
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("no revision has been published to target {from}")
+                    })?;
+            info!(
+                "Promoting revision {} from target {from} to target {name}",
+                publication.revision_id
+            );
+            Some(publication.revision_id)
+        } else {
+            self.revision
+        };
+
+        Ok(Self {
+            base_url: Some(target.base_url.parse()?),
+            build_dir: PathBuf::from(target.build_dir),
+            revision,
+            full: self.full,
+            prune: target.prune,
+            dry_run: self.dry_run,
+            atomic: target.atomic,
+            keep: usize::try_from(target.keep)?,
+            target: None,
+            promote_from: None,
+            redirects: self.redirects,
+            include_drafts: self.include_drafts,
+            now: self.now,
+            check_links: self.check_links,
+            rollback_of: self.rollback_of,
+        })
+    }
+}
+
+pub fn create(src: &Path, full: bool, cache_dir: &Path, pool: DbPool) -> anyhow::Result<()> {
     assert!(src.is_dir());
 
     info!("Scanning {}", src.display());
 
-    let rev = asset::walk(src, |evt_tx| {
-        let mut conn = pool.get()?;
-        build::create_revision(cache_dir, &evt_tx, &mut conn)
-    })?;
+    let rev = build::create(src, cache_dir, full, &pool)?;
 
     info!("Created revision {}", rev.id);
 
     Ok(())
 }
 
-pub fn publish(
-    revision: Option<i64>,
-    base_url: &Url,
-    build_dir: &Path,
-    cache_dir: &Path,
-    pool: DbPool,
-) -> anyhow::Result<()> {
+/// Finds the revision or the most recently created revision if `None`.
+fn find_revision(revision: Option<i64>, conn: &mut DbConn) -> QueryResult<Revision> {
+    if let Some(revision) = revision {
+        Revision::by_id(revision::Id(revision)).get_result(conn)
+    } else {
+        Revision::order_by_created_at_desc().first(conn)
+    }
+}
+
+/// Returns the base URL option, the site configuration's base URL or the default.
+fn resolve_base_url(base_url: Option<Url>, config: &site::Config) -> Result<Url, url::ParseError> {
+    base_url
+        .or_else(|| config.base_url.clone())
+        .map_or_else(|| Url::parse(DEFAULT_BASE_URL), Ok)
+}
+
+pub fn publish(args: &PublishArgs, cache_dir: &Path, pool: DbPool) -> anyhow::Result<()> {
     let mut conn = pool.get()?;
 
-    let rev = if let Some(revision) = revision {
-        Revision::by_id(revision::Id(revision)).get_result(&mut conn)?
+    let args = &args.with_target(&mut conn)?;
+
+    let rev = find_revision(args.revision, &mut conn)?;
+    let config = site::Config::load(&rev)?;
+    let base_url = &resolve_base_url(args.base_url.clone(), &config)?;
+    let redirects = args.redirects.or(config.redirects).unwrap_or_default();
+    let unpublished = visibility(args.include_drafts, args.now).unpublished(&rev, &mut conn)?;
+
+    if args.dry_run {
+        let outputs = publish::output_paths(&rev, base_url, redirects, &unpublished, &mut conn)?;
+        for path in prune::stale_paths(&args.build_dir, &outputs)? {
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+
+    // Checked routes are published without rendering them again.
+    let rendered = if let Some(mode) = args.check_links {
+        let (report, rendered) = links::check(&rev, base_url, cache_dir, &unpublished, &mut conn)?;
+        if !report.is_empty() {
+            eprint!("{report}");
+            if mode == links::Mode::Error {
+                anyhow::bail!(
+                    "revision {} has {} broken link(s)",
+                    rev.id,
+                    report.broken.len()
+                );
+            }
+        }
+        rendered
     } else {
-        Revision::order_by_created_at_desc().first(&mut conn)?
+        RenderedRoutes::new()
     };
 
-    info!("Building revision {} at {}", rev.id, build_dir.display());
+    if args.atomic {
+        let staging_dir = staging::create_dir(&args.build_dir, &rev)?;
+
+        info!("Building revision {} at {}", rev.id, staging_dir.display());
+
+        publish::dist_revision(
+            &staging_dir,
+            &rev,
+            base_url,
+            cache_dir,
+            true,
+            redirects,
+            rendered,
+            &unpublished,
+            &mut conn,
+        )?;
+
+        staging::activate(&args.build_dir, &staging_dir)?;
+        info!(
+            "Switched {} to {}",
+            args.build_dir.join(staging::CURRENT).display(),
+            staging_dir.display()
+        );
+
+        for dir in staging::remove_old(&args.build_dir, args.keep)? {
+            Manifest::remove(cache_dir, &dir)?;
+            info!("Removed {}", dir.display());
+        }
+
+        record_publication(
+            &rev,
+            args,
+            base_url,
+            redirects,
+            Some(&staging_dir),
+            &mut conn,
+        )?;
 
-    publish::dist_revision(build_dir, &rev, base_url, cache_dir, &mut conn)?;
+        return Ok(());
+    }
+
+    info!(
+        "Building revision {} at {}",
+        rev.id,
+        args.build_dir.display()
+    );
+
+    publish::dist_revision(
+        &args.build_dir,
+        &rev,
+        base_url,
+        cache_dir,
+        args.full,
+        redirects,
+        rendered,
+        &unpublished,
+        &mut conn,
+    )?;
+
+    if args.prune {
+        let outputs = publish::output_paths(&rev, base_url, redirects, &unpublished, &mut conn)?;
+        for path in prune::prune(&args.build_dir, &outputs)? {
+            info!("Removed {}", path.display());
+        }
+    }
+
+    record_publication(&rev, args, base_url, redirects, None, &mut conn)?;
 
     Ok(())
 }
 
+/// Returns the name used to identify a build directory in the publication history.
+///
+/// Build directories which do not exist yet are made absolute instead.
+fn target_name(build_dir: &Path) -> io::Result<String> {
+    let build_dir = match build_dir.canonicalize() {
+        Ok(build_dir) => build_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => std::path::absolute(build_dir)?,
+        Err(e) => return Err(e),
+    };
+    Ok(build_dir.to_string_lossy().to_string())
+}
+
+/// Records the publish and the options needed to repeat it.
+fn record_publication(
+    rev: &Revision,
+    args: &PublishArgs,
+    base_url: &Url,
+    redirects: redirect::Style,
+    staging_dir: Option<&Path>,
+    conn: &mut DbConn,
+) -> anyhow::Result<()> {
+    let build_dir = target_name(&args.build_dir)?;
+    let staging_dir = staging_dir
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy());
+    NewPublication {
+        revision_id: rev.id,
+        build_dir: &build_dir,
+        base_url: base_url.as_str(),
+        atomic: args.atomic,
+        prune: args.prune,
+        include_drafts: args.include_drafts,
+        staging_dir: staging_dir.as_deref(),
+        rollback_of: args.rollback_of,
+        redirects: redirects.as_str(),
+    }
+    .create(conn)?;
+    Ok(())
+}
+
 pub fn delete(revision: i64, pool: DbPool) -> anyhow::Result<()> {
     let mut conn = pool.get()?;
 
@@ -103,3 +515,661 @@ pub fn cleanup(cache_dir: &Path, pool: DbPool) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+pub fn list(json: bool, cache_dir: &Path, pool: DbPool) -> anyhow::Result<()> {
+    let mut conn = pool.get()?;
+
+    let revs = list::list(cache_dir, &mut conn)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&revs)?);
+    } else {
+        println!(
+            "{:>8}  {:<19}  {:>8}  {:>8}  {:>8}  {:>12}  {:>12}",
+            "ID", "CREATED", "FILES", "ROUTES", "PAGES", "INLINE", "CACHED"
+        );
+        for rev in revs {
+            println!(
+                "{:>8}  {:<19}  {:>8}  {:>8}  {:>8}  {:>12}  {:>12}",
+                rev.id,
+                rev.created_at.format("%Y-%m-%d %H:%M:%S"),
+                rev.files,
+                rev.routes,
+                rev.pages,
+                rev.inline_bytes,
+                rev.cached_bytes
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn diff(from: i64, to: i64, json: bool, pool: DbPool) -> anyhow::Result<()> {
+    let mut conn = pool.get()?;
+
+    let from = Revision::by_id(revision::Id(from)).get_result(&mut conn)?;
+    let to = Revision::by_id(revision::Id(to)).get_result(&mut conn)?;
+
+    let changes = diff::diff(&from, &to, &mut conn)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+        return Ok(());
+    }
+
+    if changes.is_empty() {
+        println!("No changes between revision {} and {}", from.id, to.id);
+        return Ok(());
+    }
+
+    println!("Files:");
+    for path in &changes.added_files {
+        println!("  + {path}");
+    }
+    for path in &changes.removed_files {
+        println!("  - {path}");
+    }
+    for path in &changes.changed_files {
+        println!("  ~ {path}");
+    }
+
+    println!("Routes:");
+    for route in &changes.added_routes {
+        println!("  + {route}");
+    }
+    for route in &changes.removed_routes {
+        println!("  - {route}");
+    }
+    for route in &changes.changed_routes {
+        println!("  ~ {}", route.route);
+        for field in &route.front_matter {
+            println!(
+                "      {}: {} -> {}",
+                field.field,
+                field.from.as_deref().unwrap_or("(none)"),
+                field.to.as_deref().unwrap_or("(none)")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the named target's build directory or the build directory option.
+fn target_build_dir(
+    target: Option<&str>,
+    build_dir: Option<&Path>,
+    conn: &mut DbConn,
+) -> anyhow::Result<Option<PathBuf>> {
+    if let Some(name) = target {
+        let target = Target::by_name(name)
+            .get_result(conn)
+            .optional()?
+            .ok_or_else(|| anyhow::anyhow!("target {name} does not exist"))?;
+        return Ok(Some(PathBuf::from(target.build_dir)));
+    }
+    Ok(build_dir.map(Path::to_path_buf))
+}
+
+pub fn history(
+    target: Option<&str>,
+    build_dir: Option<&Path>,
+    json: bool,
+    pool: DbPool,
+) -> anyhow::Result<()> {
+    let mut conn = pool.get()?;
+
+    let publications = if let Some(build_dir) = target_build_dir(target, build_dir, &mut conn)? {
+        Publication::by_build_dir(&target_name(&build_dir)?).load(&mut conn)?
+    } else {
+        Publication::order_by_created_at_desc().load(&mut conn)?
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&publications)?);
+    } else {
+        println!(
+            "{:>8}  {:>8}  {:<19}  {:<6}  {:<30}  BUILD DIR",
+            "ID", "REVISION", "PUBLISHED", "ATOMIC", "BASE URL"
+        );
+        for publication in publications {
+            println!(
+                "{:>8}  {:>8}  {:<19}  {:<6}  {:<30}  {}",
+                publication.id,
+                publication.revision_id,
+                publication.created_at.format("%Y-%m-%d %H:%M:%S"),
+                publication.atomic,
+                publication.base_url,
+                publication.build_dir
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the pages which are published or expire in the window and returns
+/// true if the build directory should be republished.
+pub fn due(
+    build_dir: &Path,
+    revision: Option<i64>,
+    since: Option<DateTime<FixedOffset>>,
+    now: Option<DateTime<FixedOffset>>,
+    include_drafts: bool,
+    pool: DbPool,
+) -> anyhow::Result<bool> {
+    let mut conn = pool.get()?;
+
+    let publication = Publication::by_build_dir(&target_name(build_dir)?)
+        .first(&mut conn)
+        .optional()?;
+    let last_published = || {
+        publication
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("nothing was published to {}", build_dir.display()))
+    };
+
+    let since = if let Some(since) = since {
+        since.naive_utc()
+    } else {
+        last_published()?.created_at
+    };
+    let revision = if let Some(revision) = revision {
+        revision
+    } else {
+        last_published()?.revision_id
+    };
+    let rev = Revision::by_id(revision::Id(revision)).get_result(&mut conn)?;
+
+    let include_drafts = include_drafts
+        || publication
+            .as_ref()
+            .is_some_and(|publication| publication.include_drafts);
+    let visibility = visibility(include_drafts, now);
+
+    let due = visibility.due(&rev, since, &mut conn)?;
+    for page in &due {
+        let route = Route::by_revision_id_and_input_file_id(rev.id, &page.input_file_id)
+            .first(&mut conn)
+            .optional()?;
+        println!(
+            "{:<7}  {:<19}  {}",
+            if page.is_published {
+                "publish"
+            } else {
+                "expire"
+            },
+            page.at
+                .map(|at| at.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default(),
+            route
+                .as_ref()
+                .map_or(page.input_file_id.as_str(), |route| route.route.as_str())
+        );
+    }
+
+    Ok(!due.is_empty())
+}
+
+pub fn rollback(
+    target: Option<&str>,
+    build_dir: Option<&Path>,
+    cache_dir: &Path,
+    pool: DbPool,
+) -> anyhow::Result<()> {
+    let mut conn = pool.get()?;
+
+    let named_target = target
+        .map(|name| {
+            Target::by_name(name)
+                .get_result(&mut conn)
+                .optional()?
+                .ok_or_else(|| anyhow::anyhow!("target {name} does not exist"))
+        })
+        .transpose()?;
+    let Some(target) = &target_build_dir(target, build_dir, &mut conn)? else {
+        anyhow::bail!("a target or build directory is required");
+    };
+
+    let publications = Publication::by_build_dir(&target_name(target)?).load(&mut conn)?;
+    let Some(latest) = publications.first() else {
+        anyhow::bail!("no revision has been published to {}", target.display());
+    };
+    // Walk back from the publication which is live, so repeated rollbacks go
+    // further back instead of alternating between two revisions.
+    let live_id = latest.original_id();
+    let Some(previous) = publications
+        .iter()
+        .skip_while(|publication| publication.id != live_id)
+        .skip(1)
+        .find(|publication| publication.revision_id != latest.revision_id)
+    else {
+        anyhow::bail!(
+            "no revision was published to {} before revision {}",
+            target.display(),
+            latest.revision_id
+        );
+    };
+
+    info!(
+        "Rolling back {} from revision {} to revision {}",
+        target.display(),
+        latest.revision_id,
+        previous.revision_id
+    );
+
+    // Switch back to the directory the revision was published into if it is still kept.
+    if let Some(staging_dir) = previous
+        .staging_dir
+        .as_deref()
+        .map(|name| staging::activate_existing(target, name))
+        .transpose()?
+        .flatten()
+    {
+        info!(
+            "Switched {} to {}",
+            target.join(staging::CURRENT).display(),
+            staging_dir.display()
+        );
+        NewPublication {
+            revision_id: previous.revision_id,
+            build_dir: &previous.build_dir,
+            base_url: &previous.base_url,
+            atomic: true,
+            prune: previous.prune,
+            include_drafts: previous.include_drafts,
+            staging_dir: previous.staging_dir.as_deref(),
+            rollback_of: Some(previous.original_id()),
+            redirects: &previous.redirects,
+        }
+        .create(&mut conn)?;
+        return Ok(());
+    }
+
+    let redirects =
+        redirect::Style::from_str(&previous.redirects, false).map_err(anyhow::Error::msg)?;
+
+    let args = PublishArgs {
+        base_url: Some(previous.base_url.parse()?),
+        build_dir: target.to_path_buf(),
+        revision: Some(previous.revision_id),
+        full: false,
+        prune: previous.prune,
+        dry_run: false,
+        atomic: previous.atomic,
+        keep: named_target
+            .as_ref()
+            .map(|named_target| usize::try_from(named_target.keep))
+            .transpose()?
+            .unwrap_or(DEFAULT_KEEP),
+        target: None,
+        promote_from: None,
+        redirects: Some(redirects),
+        include_drafts: previous.include_drafts,
+        now: None,
+        check_links: None,
+        rollback_of: Some(previous.original_id()),
+    };
+
+    drop(conn);
+
+    publish(&args, cache_dir, pool)
+}
+
+pub fn target(command: TargetCommand, pool: DbPool) -> anyhow::Result<()> {
+    let mut conn = pool.get()?;
+
+    match command {
+        TargetCommand::Add {
+            name,
+            base_url,
+            build_dir,
+            atomic,
+            prune,
+            keep,
+        } => {
+            let build_dir = std::path::absolute(build_dir)?;
+            let target = NewTarget {
+                name: &name,
+                base_url: base_url.as_str(),
+                build_dir: &build_dir.to_string_lossy(),
+                atomic,
+                prune,
+                keep: i64::try_from(keep)?,
+            }
+            .create(&mut conn)?;
+
+            info!("Added target {}", target.name);
+        }
+        TargetCommand::List { json } => {
+            let targets = Target::order_by_name().load(&mut conn)?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&targets)?);
+            } else {
+                println!(
+                    "{:<16}  {:<30}  {:<6}  {:<6}  {:>4}  BUILD DIR",
+                    "NAME", "BASE URL", "ATOMIC", "PRUNE", "KEEP"
+                );
+                for target in targets {
+                    println!(
+                        "{:<16}  {:<30}  {:<6}  {:<6}  {:>4}  {}",
+                        target.name,
+                        target.base_url,
+                        target.atomic,
+                        target.prune,
+                        target.keep,
+                        target.build_dir
+                    );
+                }
+            }
+        }
+        TargetCommand::Remove { name } => {
+            if Target::delete(&name, &mut conn)? == 0 {
+                anyhow::bail!("target {name} does not exist");
+            }
+
+            info!("Removed target {name}");
+        }
+    }
+
+    Ok(())
+}
+
+pub fn serve(
+    revision: Option<i64>,
+    addr: SocketAddr,
+    cache_dir: &Path,
+    hide_unpublished: bool,
+    pool: DbPool,
+) -> anyhow::Result<()> {
+    let mut conn = pool.get()?;
+
+    let rev = find_revision(revision, &mut conn)?;
+
+    serve::serve(addr, &rev, cache_dir, hide_unpublished, &mut conn)?;
+
+    Ok(())
+}
+
+/// Prints the broken links in the revision.
+///
+/// Returns true if every link is valid.
+pub fn check_links(
+    revision: Option<i64>,
+    base_url: Option<Url>,
+    include_drafts: bool,
+    now: Option<DateTime<FixedOffset>>,
+    cache_dir: &Path,
+    pool: DbPool,
+) -> anyhow::Result<bool> {
+    let mut conn = pool.get()?;
+
+    let rev = find_revision(revision, &mut conn)?;
+    let base_url = &resolve_base_url(base_url, &site::Config::load(&rev)?)?;
+    let unpublished = visibility(include_drafts, now).unpublished(&rev, &mut conn)?;
+
+    let (report, _) = links::check(&rev, base_url, cache_dir, &unpublished, &mut conn)?;
+    if report.is_empty() {
+        info!("Revision {} has no broken links", rev.id);
+    } else {
+        print!("{report}");
+    }
+
+    Ok(report.is_empty())
+}
+
+pub fn dev(
+    src: &Path,
+    addr: SocketAddr,
+    cache_dir: &Path,
+    hide_unpublished: bool,
+    pool: DbPool,
+) -> anyhow::Result<()> {
+    assert!(src.is_dir());
+
+    dev::dev(src, addr, cache_dir, hide_unpublished, &pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::testing::Site;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        publish: PublishArgs,
+    }
+
+    fn publish_args(args: &[&str]) -> PublishArgs {
+        Cli::try_parse_from([&["proj"], args].concat())
+            .unwrap()
+            .publish
+    }
+
+    fn add_target(site: &Site, name: &str) {
+        target(
+            TargetCommand::Add {
+                name: name.to_string(),
+                base_url: Url::parse(&format!("https://{name}.example.com/")).unwrap(),
+                build_dir: site.path().join(name),
+                atomic: false,
+                prune: true,
+                keep: DEFAULT_KEEP,
+            },
+            site.pool.clone(),
+        )
+        .unwrap();
+    }
+
+    /// Returns the revision last published to the named target.
+    fn published_revision(site: &Site, name: &str) -> Option<DbId> {
+        let build_dir = target_build_dir(Some(name), None, &mut site.conn())
+            .unwrap()
+            .unwrap();
+        if !build_dir.exists() {
+            return None;
+        }
+        Publication::by_build_dir(&target_name(&build_dir).unwrap())
+            .first(&mut site.conn())
+            .optional()
+            .unwrap()
+            .map(|publication| publication.revision_id)
+    }
+
+    #[test]
+    fn targets_and_promote() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write("content/index.md", "+++\ntitle = \"Home\"\n+++\nFirst\n");
+        let first = site.create();
+
+        add_target(&site, "staging");
+        add_target(&site, "production");
+        assert_eq!(
+            Target::order_by_name()
+                .load(&mut site.conn())
+                .unwrap()
+                .iter()
+                .map(|target| target.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["production", "staging"]
+        );
+
+        let publish_to = |args: &[&str]| {
+            publish(&publish_args(args), &site.cache_dir(), site.pool.clone()).unwrap();
+        };
+
+        publish_to(&["--target", "staging"]);
+        assert_eq!(published_revision(&site, "staging"), Some(first.id));
+        assert_eq!(published_revision(&site, "production"), None);
+
+        // Promoting publishes what is on staging, not the newest revision.
+        site.write("content/index.md", "+++\ntitle = \"Home\"\n+++\nSecond\n");
+        let second = site.create();
+        publish_to(&["--target", "production", "--promote-from", "staging"]);
+        assert_eq!(published_revision(&site, "production"), Some(first.id));
+        assert!(
+            fs::read_to_string(site.path().join("production/index.html"))
+                .unwrap()
+                .contains("First")
+        );
+
+        publish_to(&["--target", "staging"]);
+        assert_eq!(published_revision(&site, "staging"), Some(second.id));
+
+        assert!(publish_args(&["--target", "missing"])
+            .with_target(&mut site.conn())
+            .is_err());
+        assert!(Cli::try_parse_from(["proj", "--promote-from", "staging"]).is_err());
+
+        let remove = |name: &str| {
+            target(
+                TargetCommand::Remove {
+                    name: name.to_string(),
+                },
+                site.pool.clone(),
+            )
+        };
+        remove("staging").unwrap();
+        assert!(remove("staging").is_err());
+        assert_eq!(Target::all().load(&mut site.conn()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rollback_walks_back_with_options() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write(
+            "content/draft.md",
+            "+++\ntitle = \"Draft\"\ndraft = true\n+++\nDraft\n",
+        );
+        let mut revisions = Vec::new();
+        for body in ["First", "Second", "Third"] {
+            site.write(
+                "content/index.md",
+                &format!("+++\ntitle = \"Home\"\n+++\n{body}\n"),
+            );
+            revisions.push(site.create().id);
+            let args = publish_args(&["-b", "out", "--prune", "--include-drafts"]);
+            let args = PublishArgs {
+                build_dir: site.path().join("out"),
+                revision: revisions.last().copied(),
+                ..args
+            };
+            publish(&args, &site.cache_dir(), site.pool.clone()).unwrap();
+        }
+
+        let out = site.path().join("out");
+        let live_revision = || {
+            Publication::by_build_dir(&target_name(&out).unwrap())
+                .first(&mut site.conn())
+                .unwrap()
+                .revision_id
+        };
+        fs::write(out.join("stale.html"), "").unwrap();
+
+        rollback(None, Some(&out), &site.cache_dir(), site.pool.clone()).unwrap();
+        assert_eq!(live_revision(), revisions[1]);
+        assert!(fs::read_to_string(out.join("index.html"))
+            .unwrap()
+            .contains("Second"));
+        assert!(out.join("draft.html").exists());
+        assert!(!out.join("stale.html").exists());
+
+        // A second rollback goes further back instead of undoing the first.
+        rollback(None, Some(&out), &site.cache_dir(), site.pool.clone()).unwrap();
+        assert_eq!(live_revision(), revisions[0]);
+        assert!(rollback(None, Some(&out), &site.cache_dir(), site.pool.clone()).is_err());
+    }
+
+    #[test]
+    fn rollback_switches_to_kept_staging_dir() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        let mut staging_dirs = Vec::new();
+        for body in ["First", "Second"] {
+            site.write(
+                "content/index.md",
+                &format!("+++\ntitle = \"Home\"\n+++\n{body}\n"),
+            );
+            site.create();
+            let args = PublishArgs {
+                build_dir: site.path().join("out"),
+                ..publish_args(&["-b", "out", "--atomic"])
+            };
+            publish(&args, &site.cache_dir(), site.pool.clone()).unwrap();
+            staging_dirs.push(staging::current(&args.build_dir).unwrap().unwrap());
+        }
+
+        let out = site.path().join("out");
+        rollback(None, Some(&out), &site.cache_dir(), site.pool.clone()).unwrap();
+        assert_eq!(
+            staging::current(&out).unwrap().as_ref(),
+            Some(&staging_dirs[0])
+        );
+        let latest = Publication::by_build_dir(&target_name(&out).unwrap())
+            .first(&mut site.conn())
+            .unwrap();
+        assert_eq!(
+            latest.staging_dir.as_deref(),
+            staging_dirs[0].file_name().unwrap().to_str()
+        );
+        assert!(latest.rollback_of.is_some());
+    }
+
+    #[test]
+    fn due_after_scheduled_draft() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write(
+            "content/post.md",
+            "+++\ntitle = \"Post\"\ndraft = true\npublish_date = 2999-01-01T00:00:00Z\n+++\nPost\n",
+        );
+        site.create();
+
+        let build_dir = site.path().join("out");
+
+        // A build directory which does not exist has no publications.
+        let error = due(&build_dir, None, None, None, false, site.pool.clone()).unwrap_err();
+        assert!(
+            error.to_string().starts_with("nothing was published to"),
+            "{error}"
+        );
+
+        let due = |include_drafts: bool, now: &str| {
+            due(
+                &build_dir,
+                None,
+                None,
+                Some(DateTime::parse_from_rfc3339(now).unwrap()),
+                include_drafts,
+                site.pool.clone(),
+            )
+            .unwrap()
+        };
+        let publish = |args: &[&str]| {
+            let args = PublishArgs {
+                build_dir: build_dir.clone(),
+                ..publish_args(args)
+            };
+            publish(&args, &site.cache_dir(), site.pool.clone()).unwrap();
+        };
+
+        // The draft is never published.
+        publish(&["-b", "out"]);
+        assert!(!due(false, "3000-01-01T00:00:00Z"));
+        assert!(due(true, "3000-01-01T00:00:00Z"));
+
+        // Unless drafts were included in the last publish.
+        publish(&["-b", "out", "--include-drafts"]);
+        assert!(!due(false, "2998-01-01T00:00:00Z"));
+        assert!(due(false, "3000-01-01T00:00:00Z"));
+    }
+}
@@ -0,0 +1,562 @@
+//! Lists of pages available to templates.
+//!
+//! The published pages of a revision are loaded once and exposed with these
+//! Handlebars helpers:
+//!
+//! - `pages`: pages sorted by `date` with the newest first. The optional hash
+//!   arguments are `section` to only include pages in a directory under
+//!   `content/` (including nested directories), `tag`, `sort` (`date`, `title`
+//!   or `route`), `order` (`asc` or `desc`) and `limit`. Section index pages
+//!   are not included.
+//! - `children`: pages directly in a section and the index pages of its
+//!   immediate subsections. The section is the current page's directory
+//!   unless a `section` hash argument is given.
+//! - `prev_page` and `next_page`: the previous (older) and next (newer) page
+//!   by date in the current page's section.
+//!
+//! Every page has the same data as `page` in [`crate::context`] with its
+//! `route`, `url`, `section` and `headings`, a list of the page's headings
+//! with their `level`, `id`, `title` and `url`.
+//!
+//! ```handlebars
+//! {{#each (pages section="blog" limit=10)}}
+//!   <a href="{{url}}">{{title}}</a>
+//! {{/each}}
+//! {{#with (next_page)}}<a href="{{url}}">{{title}}</a>{{/with}}
+//! ```
+
+use std::{cmp::Ordering, collections::BTreeMap, path::Path, sync::Arc};
+
+use chrono::NaiveDateTime;
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError, RenderErrorReason,
+    ScopedJson,
+};
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::{
+    context,
+    models::{
+        input_file::{self, InputFileMeta, Ty},
+        page::Page,
+        page_heading::PageHeading,
+        page_tag::PageTag,
+        revision::Revision,
+        route::Route,
+        DbConn,
+    },
+    visibility::Unpublished,
+};
+
+/// Names of the registered helpers.
+pub const HELPERS: &[&str] = &["pages", "children", "prev_page", "next_page"];
+
+#[derive(Debug)]
+struct Entry {
+    /// Directory of the page relative to `content/`.
+    section: String,
+    /// True if the page is the index of its section.
+    is_index: bool,
+    route: String,
+    date: Option<NaiveDateTime>,
+    title: Option<String>,
+    tags: Vec<String>,
+    data: Value,
+}
+
+impl Entry {
+    /// Section which lists the page as a child.
+    fn parent_section(&self) -> &str {
+        if self.is_index {
+            self.section
+                .rsplit_once('/')
+                .map_or("", |(parent, _)| parent)
+        } else {
+            &self.section
+        }
+    }
+
+    fn is_in(&self, section: &str) -> bool {
+        section.is_empty()
+            || self.section == section
+            || self
+                .section
+                .strip_prefix(section)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// The published pages of a revision.
+#[derive(Debug)]
+pub struct Collection {
+    entries: Vec<Entry>,
+}
+
+impl Collection {
+    /// Loads the pages in the revision which are not `unpublished`.
+    pub fn load(
+        rev: &Revision,
+        base_url: &Url,
+        unpublished: &Unpublished,
+        conn: &mut DbConn,
+    ) -> anyhow::Result<Self> {
+        let routes = Route::with_revision(rev, conn)?
+            .into_iter()
+            .map(|r| (r.input_file_id, r.route))
+            .collect::<BTreeMap<_, _>>();
+        let logical_paths = InputFileMeta::with_revision(rev, conn)?
+            .into_iter()
+            .map(|f| (f.id, f.logical_path))
+            .collect::<BTreeMap<_, _>>();
+        let mut headings = BTreeMap::<String, Vec<PageHeading>>::new();
+        for heading in PageHeading::with_revision(rev, conn)? {
+            headings
+                .entry(heading.input_file_id.clone())
+                .or_default()
+                .push(heading);
+        }
+        let mut tags = BTreeMap::<String, Vec<String>>::new();
+        for page_tag in PageTag::with_revision(rev, conn)? {
+            tags.entry(page_tag.input_file_id)
+                .or_default()
+                .push(page_tag.tag);
+        }
+
+        let mut entries = Vec::new();
+        for page in Page::with_revision(rev, conn)? {
+            if unpublished.contains_key(&page.input_file_id) {
+                continue;
+            }
+            let (Some(route), Some(logical_path)) = (
+                routes.get(&page.input_file_id),
+                logical_paths.get(&page.input_file_id),
+            ) else {
+                continue;
+            };
+            let Ty::Content(path) = input_file::ty(logical_path) else {
+                continue;
+            };
+
+            let path = Path::new(path);
+            let section = path
+                .parent()
+                .map(|parent| parent.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let is_index = path
+                .file_stem()
+                .is_some_and(|stem| stem == "index" || stem == "_index");
+
+            let url = base_url.join(route)?;
+            let headings = headings
+                .remove(&page.input_file_id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|heading| {
+                    let mut url = url.clone();
+                    url.set_fragment(Some(&heading.anchor));
+                    json!({
+                        "level": heading.level,
+                        "id": heading.anchor,
+                        "title": heading.title,
+                        "url": url.as_str(),
+                    })
+                })
+                .collect();
+
+            let mut data = context::page(&page)?;
+            if let Value::Object(data) = &mut data {
+                data.insert("route".to_string(), Value::from(route.as_str()));
+                data.insert("url".to_string(), Value::from(url.as_str()));
+                data.insert("section".to_string(), Value::from(section.as_str()));
+                data.insert("headings".to_string(), Value::Array(headings));
+            }
+
+            entries.push(Entry {
+                section,
+                is_index,
+                route: route.clone(),
+                date: page.date,
+                title: page.title,
+                tags: tags.remove(&page.input_file_id).unwrap_or_default(),
+                data,
+            });
+        }
+
+        entries.sort_by(|a, b| by_date(a, b).then_with(|| a.route.cmp(&b.route)));
+
+        Ok(Self { entries })
+    }
+
+    /// Returns the inputs which affect pages rendered with the helpers.
+    pub fn dependencies(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| format!("{}\n{}", entry.route, entry.data))
+            .collect()
+    }
+
+    fn find(&self, route: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.route == route)
+    }
+}
+
+/// Orders pages by date with pages without a date first.
+fn by_date(a: &Entry, b: &Entry) -> Ordering {
+    a.date.cmp(&b.date).then_with(|| a.title.cmp(&b.title))
+}
+
+fn hash_str<'a>(
+    helper: &'static str,
+    h: &'a Helper<'_>,
+    name: &str,
+) -> Result<Option<&'a str>, RenderError> {
+    h.hash_get(name)
+        .map(|value| {
+            value.value().as_str().ok_or_else(|| {
+                RenderErrorReason::HashTypeMismatchForName(
+                    helper,
+                    name.to_string(),
+                    "string".to_string(),
+                )
+                .into()
+            })
+        })
+        .transpose()
+}
+
+/// Returns the route of the page being rendered.
+fn current_route(ctx: &Context) -> Option<&str> {
+    ctx.data().get("route").and_then(Value::as_str)
+}
+
+fn to_json<'a>(entries: impl Iterator<Item = &'a Entry>) -> ScopedJson<'static> {
+    ScopedJson::Derived(Value::Array(
+        entries.map(|entry| entry.data.clone()).collect(),
+    ))
+}
+
+struct PagesHelper(Arc<Collection>);
+
+impl HelperDef for PagesHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let section = hash_str("pages", h, "section")?.unwrap_or_default();
+        let tag = hash_str("pages", h, "tag")?;
+        let sort = hash_str("pages", h, "sort")?.unwrap_or("date");
+        let order = hash_str("pages", h, "order")?;
+        let limit = h
+            .hash_get("limit")
+            .map(|value| {
+                value
+                    .value()
+                    .as_u64()
+                    .and_then(|limit| usize::try_from(limit).ok())
+                    .ok_or_else(|| {
+                        RenderErrorReason::HashTypeMismatchForName(
+                            "pages",
+                            "limit".to_string(),
+                            "number".to_string(),
+                        )
+                    })
+            })
+            .transpose()?;
+
+        let mut entries = self
+            .0
+            .entries
+            .iter()
+            .filter(|entry| !entry.is_index && entry.is_in(section))
+            .filter(|entry| tag.is_none_or(|tag| entry.tags.iter().any(|t| t == tag)))
+            .collect::<Vec<_>>();
+
+        match sort {
+            "date" => {}
+            "title" => entries.sort_by(|a, b| a.title.cmp(&b.title)),
+            "route" => entries.sort_by(|a, b| a.route.cmp(&b.route)),
+            _ => {
+                return Err(RenderErrorReason::HashTypeMismatchForName(
+                    "pages",
+                    "sort".to_string(),
+                    "date, title or route".to_string(),
+                )
+                .into())
+            }
+        }
+
+        let is_desc = match order {
+            None => sort == "date",
+            Some("asc") => false,
+            Some("desc") => true,
+            Some(_) => {
+                return Err(RenderErrorReason::HashTypeMismatchForName(
+                    "pages",
+                    "order".to_string(),
+                    "asc or desc".to_string(),
+                )
+                .into())
+            }
+        };
+        if is_desc {
+            entries.reverse();
+        }
+
+        Ok(to_json(
+            entries.into_iter().take(limit.unwrap_or(usize::MAX)),
+        ))
+    }
+}
+
+struct ChildrenHelper(Arc<Collection>);
+
+impl HelperDef for ChildrenHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let section = if let Some(section) = hash_str("children", h, "section")? {
+            section.to_string()
+        } else {
+            current_route(ctx)
+                .and_then(|route| self.0.find(route))
+                .map(|entry| entry.section.clone())
+                .unwrap_or_default()
+        };
+
+        Ok(to_json(self.0.entries.iter().filter(|entry| {
+            entry.parent_section() == section && !(entry.is_index && entry.section == section)
+        })))
+    }
+}
+
+/// Returns the page before or after the current page in its section.
+struct SiblingHelper {
+    collection: Arc<Collection>,
+    is_next: bool,
+}
+
+impl HelperDef for SiblingHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        _: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let Some(current) = current_route(ctx).and_then(|route| self.collection.find(route)) else {
+            return Ok(ScopedJson::Missing);
+        };
+
+        let siblings = self
+            .collection
+            .entries
+            .iter()
+            .filter(|entry| !entry.is_index && entry.section == current.section)
+            .collect::<Vec<_>>();
+        let Some(position) = siblings
+            .iter()
+            .position(|entry| entry.route == current.route)
+        else {
+            return Ok(ScopedJson::Missing);
+        };
+
+        let sibling = if self.is_next {
+            siblings.get(position + 1)
+        } else {
+            position.checked_sub(1).and_then(|i| siblings.get(i))
+        };
+
+        Ok(sibling.map_or(ScopedJson::Missing, |entry| {
+            ScopedJson::Derived(entry.data.clone())
+        }))
+    }
+}
+
+/// Registers the collection helpers.
+pub fn register(templates: &mut Handlebars<'_>, collection: &Arc<Collection>) {
+    templates.register_helper("pages", Box::new(PagesHelper(Arc::clone(collection))));
+    templates.register_helper("children", Box::new(ChildrenHelper(Arc::clone(collection))));
+    templates.register_helper(
+        "prev_page",
+        Box::new(SiblingHelper {
+            collection: Arc::clone(collection),
+            is_next: false,
+        }),
+    );
+    templates.register_helper(
+        "next_page",
+        Box::new(SiblingHelper {
+            collection: Arc::clone(collection),
+            is_next: true,
+        }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::{testing::Site, visibility::Reason};
+
+    fn entry(path: &str, day: Option<u32>, tags: &[&str]) -> Entry {
+        let path = Path::new(path);
+        let section = path
+            .parent()
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let is_index = path.file_stem().is_some_and(|stem| stem == "index");
+        let route = path.with_extension("html").to_string_lossy().to_string();
+        let title = path.file_stem().unwrap().to_string_lossy().to_string();
+        Entry {
+            section,
+            is_index,
+            date: day.map(|day| {
+                NaiveDate::from_ymd_opt(2024, 1, day)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+            }),
+            tags: tags.iter().map(|tag| (*tag).to_string()).collect(),
+            data: json!({ "title": title, "route": route }),
+            title: Some(title),
+            route,
+        }
+    }
+
+    fn templates() -> Handlebars<'static> {
+        let mut entries = vec![
+            entry("index.md", None, &[]),
+            entry("about.md", None, &[]),
+            entry("blog/index.md", None, &[]),
+            entry("blog/b.md", Some(2), &["rust"]),
+            entry("blog/a.md", Some(1), &[]),
+            entry("blog/c.md", Some(3), &["rust"]),
+            entry("blog/2024/index.md", None, &[]),
+            entry("blog/2024/d.md", Some(4), &[]),
+        ];
+        entries.sort_by(|a, b| by_date(a, b).then_with(|| a.route.cmp(&b.route)));
+
+        let mut templates = Handlebars::new();
+        register(&mut templates, &Arc::new(Collection { entries }));
+        templates
+    }
+
+    fn render(template: &str, route: &str) -> String {
+        templates()
+            .render_template(template, &json!({ "route": route }))
+            .unwrap()
+    }
+
+    fn titles(args: &str) -> String {
+        render(
+            &format!("{{{{#each (pages {args})}}}}{{{{title}}}},{{{{/each}}}}"),
+            "index.html",
+        )
+    }
+
+    #[test]
+    fn pages_in_section() {
+        assert_eq!(titles(""), "d,c,b,a,about,");
+        assert_eq!(titles("section=\"blog\""), "d,c,b,a,");
+        assert_eq!(titles("section=\"blog/2024\""), "d,");
+        assert_eq!(titles("section=\"blo\""), "");
+        assert_eq!(titles("section=\"blog\" tag=\"rust\""), "c,b,");
+    }
+
+    #[test]
+    fn pages_sort_and_limit() {
+        assert_eq!(titles("section=\"blog\" order=\"asc\""), "a,b,c,d,");
+        assert_eq!(titles("section=\"blog\" sort=\"title\""), "a,b,c,d,");
+        assert_eq!(
+            titles("section=\"blog\" sort=\"route\" order=\"desc\""),
+            "c,b,a,d,"
+        );
+        assert_eq!(titles("section=\"blog\" limit=2"), "d,c,");
+        assert_eq!(titles("section=\"blog\" limit=0"), "");
+
+        let templates = templates();
+        for args in ["sort=\"size\"", "order=\"up\"", "limit=\"2\""] {
+            assert!(templates
+                .render_template(
+                    &format!("{{{{#each (pages {args})}}}}{{{{/each}}}}"),
+                    &json!({})
+                )
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn children_of_section() {
+        let children = "{{#each (children)}}{{title}},{{/each}}";
+        assert_eq!(render(children, "index.html"), "about,index,");
+        // Pages without a date are first.
+        assert_eq!(render(children, "blog/a.html"), "index,a,b,c,");
+        assert_eq!(
+            render(
+                "{{#each (children section=\"blog/2024\")}}{{title}},{{/each}}",
+                "index.html"
+            ),
+            "d,"
+        );
+    }
+
+    #[test]
+    fn prev_and_next_at_boundaries() {
+        let siblings =
+            "{{#with (prev_page)}}{{title}}{{/with}}|{{#with (next_page)}}{{title}}{{/with}}";
+        assert_eq!(render(siblings, "blog/a.html"), "|b");
+        assert_eq!(render(siblings, "blog/b.html"), "a|c");
+        assert_eq!(render(siblings, "blog/c.html"), "b|");
+        // Pages in subsections and section indexes are not siblings.
+        assert_eq!(render(siblings, "blog/2024/d.html"), "|");
+        assert_eq!(render(siblings, "blog/index.html"), "|");
+        assert_eq!(render(siblings, "missing.html"), "|");
+    }
+
+    #[test]
+    fn unpublished_pages_are_excluded() {
+        let site = Site::new();
+        site.write(
+            "content/blog/a.md",
+            "+++\ntitle = \"A\"\ndate = 2024-01-01\n+++\nA\n",
+        );
+        site.write(
+            "content/blog/b.md",
+            "+++\ntitle = \"B\"\ndate = 2024-01-02\n+++\nB\n",
+        );
+        let rev = site.create();
+        let base_url = Url::parse("https://example.com/").unwrap();
+        let mut conn = site.conn();
+
+        let collection = Collection::load(&rev, &base_url, &Unpublished::new(), &mut conn).unwrap();
+        assert_eq!(collection.entries.len(), 2);
+        let b = collection.find("blog/b.html").unwrap();
+        assert_eq!(b.data["url"], "https://example.com/blog/b.html");
+        assert_eq!(b.section, "blog");
+
+        let draft = InputFileMeta::with_revision(&rev, &mut conn)
+            .unwrap()
+            .into_iter()
+            .find(|f| f.logical_path == "content/blog/b.md")
+            .unwrap();
+        let unpublished = Unpublished::from([(draft.id, Reason::Draft)]);
+        let collection = Collection::load(&rev, &base_url, &unpublished, &mut conn).unwrap();
+        assert_eq!(
+            collection
+                .entries
+                .iter()
+                .map(|entry| entry.route.as_str())
+                .collect::<Vec<_>>(),
+            vec!["blog/a.html"]
+        );
+    }
+}
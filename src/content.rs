@@ -1,5 +1,6 @@
 use chrono::prelude::*;
-use toml_edit::Datetime;
+use serde_json::{Map, Value};
+use toml_edit::{Datetime, Document, Item};
 
 #[derive(Debug)]
 enum State {
@@ -59,14 +60,10 @@ pub fn parse(contents: &str) -> Result<(Option<&str>, usize, &str), Error> {
                 '+' => {
                     *marker_count += 1;
                 }
-                '\n' | '\r' => {
-                    if *marker_count >= 3 {
-                        state = State::EndedBeginMarker {
-                            marker_count: *marker_count,
-                        };
-                    } else {
-                        return Err(Error::InvalidStartMarker);
-                    }
+                '\n' | '\r' if *marker_count >= 3 => {
+                    state = State::EndedBeginMarker {
+                        marker_count: *marker_count,
+                    };
                 }
                 _ => {
                     return Err(Error::InvalidStartMarker);
@@ -200,6 +197,58 @@ pub fn convert_datetime(value: &Datetime) -> chrono::NaiveDateTime {
     }
 }
 
+fn value_to_json(value: &toml_edit::Value) -> Value {
+    match value {
+        toml_edit::Value::String(s) => Value::from(s.value().as_str()),
+        toml_edit::Value::Integer(i) => Value::from(*i.value()),
+        toml_edit::Value::Float(f) => Value::from(*f.value()),
+        toml_edit::Value::Boolean(b) => Value::from(*b.value()),
+        toml_edit::Value::Datetime(dt) => Value::from(dt.value().to_string()),
+        toml_edit::Value::Array(array) => array.iter().map(value_to_json).collect(),
+        toml_edit::Value::InlineTable(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.to_string(), value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn item_to_json(item: &Item) -> Value {
+    match item {
+        Item::None => Value::Null,
+        Item::Value(value) => value_to_json(value),
+        Item::Table(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, item)| (key.to_string(), item_to_json(item)))
+                .collect(),
+        ),
+        Item::ArrayOfTables(tables) => tables
+            .iter()
+            .map(|table| {
+                Value::Object(
+                    table
+                        .iter()
+                        .map(|(key, item)| (key.to_string(), item_to_json(item)))
+                        .collect(),
+                )
+            })
+            .collect(),
+    }
+}
+
+/// Converts the front matter into JSON.
+///
+/// Datetimes are converted into strings as written in the front matter.
+pub fn front_matter_json(front_matter: &str) -> Result<Map<String, Value>, toml_edit::TomlError> {
+    let doc = front_matter.parse::<Document>()?;
+    Ok(doc
+        .iter()
+        .map(|(key, item)| (key.to_string(), item_to_json(item)))
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,4 +321,31 @@ Hello."#;
             parse(input)
         );
     }
+
+    #[test]
+    fn front_matter_as_json() {
+        let front_matter = r#"
+title = "Hello World!"
+tags = ["a", "b"]
+draft = false
+weight = 3
+date = 2023-04-08T15:20:57Z
+
+[author]
+name = "Example"
+"#;
+
+        let json = front_matter_json(front_matter).unwrap();
+        assert_eq!(
+            Value::Object(json),
+            serde_json::json!({
+                "title": "Hello World!",
+                "tags": ["a", "b"],
+                "draft": false,
+                "weight": 3,
+                "date": "2023-04-08T15:20:57Z",
+                "author": { "name": "Example" },
+            })
+        );
+    }
 }
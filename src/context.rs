@@ -0,0 +1,92 @@
+//! Data passed to templates.
+//!
+//! Every template is rendered with:
+//!
+//! - `site.base_url`: the URL the site is published at
+//! - `site.title`, `site.description`, `site.author`, `site.language` and
+//!   `site.extra`: the values in the revision's [`crate::site`] configuration
+//! - `revision.id` and `revision.created_at`: the revision being published
+//! - `route`: the route being rendered relative to the base URL
+//! - `url`: the absolute URL of the route
+//!
+//! Content pages are also rendered with:
+//!
+//! - `content`: the page's Markdown rendered as HTML
+//! - `toc`: the page's headings as a table of contents. See
+//!   [`crate::markdown::toc`].
+//! - `page`: every key in the page's front matter including keys which are
+//!   not otherwise used. `title`, `date`, `description`, `excerpt`, `draft`,
+//!   `expiry_date`, `keywords`, `template`, `publish_date` and `summary` are
+//!   always present with dates converted to UTC. `tags` and `aliases` are
+//!   always arrays.
+//!
+//! Tag pages are also rendered with the data described in [`crate::taxonomy`].
+
+use serde_json::{json, Map, Value};
+use url::Url;
+
+use crate::{
+    content,
+    models::{page::Page, revision::Revision},
+    site,
+};
+
+/// Returns the data passed to every template.
+pub fn common(
+    rev: &Revision,
+    config: &site::Config,
+    base_url: &Url,
+    route: &str,
+) -> anyhow::Result<Map<String, Value>> {
+    let mut data = Map::new();
+    data.insert(
+        "site".to_string(),
+        json!({
+            "base_url": base_url.as_str(),
+            "title": config.title,
+            "description": config.description,
+            "author": config.author,
+            "language": config.language,
+            "extra": config.extra,
+        }),
+    );
+    data.insert(
+        "revision".to_string(),
+        json!({ "id": rev.id, "created_at": rev.created_at }),
+    );
+    data.insert("route".to_string(), Value::from(route));
+    data.insert(
+        "url".to_string(),
+        Value::from(base_url.join(route)?.as_str()),
+    );
+    Ok(data)
+}
+
+/// Returns the `page` data for a content page.
+pub fn page(page: &Page) -> anyhow::Result<Value> {
+    let mut data = page
+        .front_matter
+        .as_deref()
+        .map(content::front_matter_json)
+        .transpose()?
+        .unwrap_or_default();
+
+    for key in ["tags", "aliases"] {
+        data.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+    }
+
+    data.extend([
+        ("title".to_string(), json!(page.title)),
+        ("date".to_string(), json!(page.date)),
+        ("description".to_string(), json!(page.description)),
+        ("excerpt".to_string(), json!(page.excerpt)),
+        ("draft".to_string(), json!(page.draft)),
+        ("expiry_date".to_string(), json!(page.expiry_date)),
+        ("keywords".to_string(), json!(page.keywords)),
+        ("template".to_string(), json!(page.template)),
+        ("publish_date".to_string(), json!(page.publish_date)),
+        ("summary".to_string(), json!(page.summary)),
+    ]);
+
+    Ok(Value::Object(data))
+}
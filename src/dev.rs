@@ -0,0 +1,366 @@
+//! Development mode.
+//!
+//! Watches the source directories, creates a new revision when a file changes,
+//! serves the latest revision, and reloads open pages in the browser.
+
+use std::{
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use diesel::prelude::*;
+use notify::{event::ModifyKind, EventKind, RecursiveMode, Watcher};
+use tiny_http::{Request, Server};
+use url::Url;
+
+use crate::{
+    asset, build, delete, diff,
+    models::{
+        revision::{self, Revision},
+        DbConn, DbId, DbPool,
+    },
+    publish::{self, Templates},
+    serve::{self, Context},
+    site,
+    visibility::Unpublished,
+};
+
+/// Path of the server-sent events endpoint which notifies pages of a new revision.
+const RELOAD_PATH: &str = "/_proj/reload";
+
+/// Script added to HTML pages to reload the page when a new revision is created.
+const RELOAD_SCRIPT: &str = r#"<script>new EventSource("/_proj/reload").addEventListener("reload", () => location.reload());</script>"#;
+
+/// Time to wait for more file events before creating a revision.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Interval to check whether to stop watching when there are no file events.
+const STOP_POLL: Duration = Duration::from_millis(250);
+
+/// Interval to send comments on idle event streams to detect closed connections.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Default)]
+struct Reloader {
+    clients: Mutex<Vec<mpsc::Sender<DbId>>>,
+}
+
+impl Reloader {
+    fn subscribe(&self) -> mpsc::Receiver<DbId> {
+        let (tx, rx) = mpsc::channel();
+        self.clients.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn notify(&self, revision_id: DbId) {
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(revision_id).is_ok());
+    }
+
+    /// Ends the event streams of every client.
+    fn close(&self) {
+        self.clients.lock().unwrap().clear();
+    }
+}
+
+fn stream_reloads(request: Request, reloads: &mpsc::Receiver<DbId>) -> io::Result<()> {
+    let mut writer = request.into_writer();
+    writer.write_all(
+        b"HTTP/1.1 200 OK\r\n\
+        Content-Type: text/event-stream\r\n\
+        Cache-Control: no-cache\r\n\
+        Connection: close\r\n\r\n",
+    )?;
+    writer.flush()?;
+
+    loop {
+        match reloads.recv_timeout(KEEP_ALIVE) {
+            Ok(revision_id) => write!(writer, "event: reload\ndata: {revision_id}\n\n")?,
+            Err(RecvTimeoutError::Timeout) => writer.write_all(b": keep-alive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        writer.flush()?;
+    }
+}
+
+/// Creates a revision and returns it if it differs from the previous revision.
+///
+/// Unchanged revisions are deleted.
+fn create_if_changed(
+    src: &Path,
+    cache_dir: &Path,
+    previous_id: DbId,
+    pool: &DbPool,
+) -> anyhow::Result<Option<Revision>> {
+    let rev = build::create(src, cache_dir, false, pool)?;
+
+    let mut conn = pool.get()?;
+    let previous = Revision::by_id(revision::Id(previous_id)).get_result(&mut conn)?;
+    if previous.site_config == rev.site_config && diff::diff(&previous, &rev, &mut conn)?.is_empty()
+    {
+        delete::delete(&rev, &mut conn)?;
+        return Ok(None);
+    }
+
+    Ok(Some(rev))
+}
+
+/// Returns the path with its parent directory canonicalized.
+///
+/// Removed files can not be canonicalized but their directory usually can.
+fn canonicalize_parent(path: &Path) -> PathBuf {
+    path.parent()
+        .and_then(|parent| parent.canonicalize().ok())
+        .zip(path.file_name())
+        .map_or_else(|| path.to_path_buf(), |(parent, name)| parent.join(name))
+}
+
+/// Returns true if the path is directly in the canonical source directory
+/// and is one of its source directories.
+fn is_src_sub_dir(src: &Path, path: &Path) -> bool {
+    path.parent() == Some(src)
+        && path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| asset::SRC_SUB_DIRS.contains(&name))
+}
+
+/// Returns true if a change to the path may change the site.
+///
+/// `src` is the canonical source directory. Other files in it such as the
+/// database are not part of the site.
+fn is_site_path(src: &Path, path: &Path) -> bool {
+    let path = canonicalize_parent(path);
+    path.parent() != Some(src) || path == src.join(site::FILE_NAME) || is_src_sub_dir(src, &path)
+}
+
+/// Creates a revision when a source file changes until `stop` is set.
+fn watch(
+    src: &Path,
+    cache_dir: &Path,
+    current: &AtomicI64,
+    reloader: &Reloader,
+    stop: &AtomicBool,
+    pool: &DbPool,
+) -> anyhow::Result<()> {
+    // Watchers may report absolute paths so they are compared canonicalized.
+    let src = &src.canonicalize()?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    for &prefix in asset::SRC_SUB_DIRS {
+        let dir = src.join(prefix);
+        if dir.is_dir() {
+            watcher.watch(&dir, RecursiveMode::Recursive)?;
+        }
+    }
+    // The configuration file may be replaced so its directory is watched instead.
+    watcher.watch(src, RecursiveMode::NonRecursive)?;
+
+    while !stop.load(Ordering::Acquire) {
+        let event = match rx.recv_timeout(STOP_POLL) {
+            Ok(event) => event?,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            continue;
+        }
+        if !event.paths.iter().any(|path| is_site_path(src, path)) {
+            continue;
+        }
+        // Source directories created or moved in after starting are watched too.
+        if matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+        ) {
+            for path in event.paths.iter().map(|path| canonicalize_parent(path)) {
+                if is_src_sub_dir(src, &path) && path.is_dir() {
+                    watcher.watch(&path, RecursiveMode::Recursive)?;
+                }
+            }
+        }
+
+        // Editors may write several files or write a file in several steps.
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+        match create_if_changed(src, cache_dir, current.load(Ordering::Acquire), pool) {
+            Ok(Some(rev)) => {
+                tracing::info!("Created revision {}", rev.id);
+                current.store(rev.id, Ordering::Release);
+                reloader.notify(rev.id);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("Could not create revision: {e:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn dev(
+    src: &Path,
+    addr: SocketAddr,
+    cache_dir: &Path,
+    hide_unpublished: bool,
+    pool: &DbPool,
+) -> anyhow::Result<()> {
+    let rev = build::create(src, cache_dir, false, pool)?;
+    tracing::info!("Created revision {}", rev.id);
+
+    let current = AtomicI64::new(rev.id);
+    let reloader = Reloader::default();
+
+    let server = Server::http(addr).map_err(|e| anyhow::anyhow!(e))?;
+    let ctx = Context {
+        base_url: Url::parse(&format!("http://{addr}/"))?,
+        cache_dir,
+        body_html: Some(RELOAD_SCRIPT),
+        hide_unpublished,
+    };
+
+    tracing::info!("Serving at {}", ctx.base_url);
+
+    let stop = AtomicBool::new(false);
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            if let Err(e) = watch(src, cache_dir, &current, &reloader, &stop, pool) {
+                tracing::error!("Stopped watching for changes: {e:?}");
+            }
+        });
+
+        let prepare = |revision_id: DbId, conn: &mut DbConn| -> anyhow::Result<_> {
+            let rev = Revision::by_id(revision::Id(revision_id)).get_result(conn)?;
+            let unpublished = ctx.unpublished(&rev, conn)?;
+            let templates = publish::templates(&rev, &ctx.base_url, &unpublished, conn)?;
+            Ok((rev, templates, unpublished))
+        };
+
+        let result = (|| -> anyhow::Result<()> {
+            let mut conn = pool.get()?;
+            let mut prepared: Option<(Revision, Templates, Unpublished)> = None;
+
+            for request in server.incoming_requests() {
+                if request.url() == RELOAD_PATH {
+                    let reloads = reloader.subscribe();
+                    s.spawn(move || {
+                        if let Err(e) = stream_reloads(request, &reloads) {
+                            tracing::debug!("Closed reload stream: {e}");
+                        }
+                    });
+                    continue;
+                }
+
+                // Requests are answered with an error until the current
+                // revision can be prepared.
+                let revision_id = current.load(Ordering::Acquire);
+                if prepared
+                    .as_ref()
+                    .is_none_or(|(rev, _, _)| rev.id != revision_id)
+                {
+                    prepared = match prepare(revision_id, &mut conn) {
+                        Ok(prepared) => Some(prepared),
+                        Err(e) => {
+                            tracing::error!("Could not prepare revision {revision_id}: {e:?}");
+                            if let Err(e) = request.respond(serve::internal_server_error()) {
+                                tracing::warn!("Could not send response: {e}");
+                            }
+                            continue;
+                        }
+                    };
+                }
+                let (rev, templates, unpublished) = prepared.as_ref().unwrap();
+
+                serve::handle(request, rev, &ctx, templates, unpublished, &mut conn);
+            }
+
+            Ok(())
+        })();
+
+        // The scope waits for the watcher and the reload streams to end.
+        stop.store(true, Ordering::Release);
+        reloader.close();
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::{in_thread_pool, Site};
+
+    #[test]
+    fn create_only_changed_revisions() {
+        let site = Site::new();
+        fs::remove_dir(site.src().join("templates")).unwrap();
+        site.write("content/index.md", "+++\ntitle = \"Home\"\n+++\nHome\n");
+        let first = site.create();
+        let create_if_changed = || {
+            in_thread_pool(|| {
+                create_if_changed(&site.src(), &site.cache_dir(), first.id, &site.pool)
+            })
+            .unwrap()
+        };
+
+        assert_eq!(create_if_changed(), None);
+        let ids = || {
+            Revision::all()
+                .load(&mut site.conn())
+                .unwrap()
+                .into_iter()
+                .map(|rev| rev.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(), vec![first.id]);
+
+        // A source directory created later is part of the site.
+        site.write("templates/default.hbs", "{{{content}}}");
+        let second = create_if_changed().unwrap();
+        assert_eq!(ids(), vec![first.id, second.id]);
+    }
+
+    #[test]
+    fn site_paths() {
+        let site = Site::new();
+        let src = site.src().canonicalize().unwrap();
+
+        assert!(is_site_path(&src, &src.join("content/index.md")));
+        assert!(is_site_path(&src, &src.join("templates")));
+        assert!(is_site_path(&src, &src.join(site::FILE_NAME)));
+        assert!(!is_site_path(&src, &src.join("site.db")));
+        assert!(!is_site_path(&src, &src.join("site.db-journal")));
+
+        assert!(is_site_path(
+            &src,
+            &site.path().join("src/./static/..").join("site.toml")
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_site_paths() {
+        let site = Site::new();
+        let src = site.src().canonicalize().unwrap();
+
+        // Paths through other names of the source directory are the same paths.
+        let link = site.path().join("link");
+        std::os::unix::fs::symlink(&src, &link).unwrap();
+        assert!(!is_site_path(&src, &link.join("site.db")));
+        assert!(is_site_path(&src, &link.join(site::FILE_NAME)));
+    }
+}
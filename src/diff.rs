@@ -0,0 +1,267 @@
+//! Compares two revisions.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use diesel::prelude::*;
+use serde_derive::Serialize;
+use toml_edit::{Document, Item};
+
+use crate::models::{
+    input_file::InputFileMeta, page::Page, revision::Revision, route::Route, DbConn, DbId,
+};
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangedRoute {
+    pub route: String,
+    pub from_input_file_id: String,
+    pub to_input_file_id: String,
+    /// Front matter fields which differ if both input files are pages.
+    pub front_matter: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionDiff {
+    pub from: DbId,
+    pub to: DbId,
+    pub added_files: Vec<String>,
+    pub removed_files: Vec<String>,
+    pub changed_files: Vec<String>,
+    pub added_routes: Vec<String>,
+    pub removed_routes: Vec<String>,
+    pub changed_routes: Vec<ChangedRoute>,
+}
+
+impl RevisionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_files.is_empty()
+            && self.removed_files.is_empty()
+            && self.changed_files.is_empty()
+            && self.added_routes.is_empty()
+            && self.removed_routes.is_empty()
+            && self.changed_routes.is_empty()
+    }
+}
+
+fn front_matter_fields(front_matter: Option<&str>) -> anyhow::Result<BTreeMap<String, String>> {
+    let doc = front_matter.unwrap_or_default().parse::<Document>()?;
+
+    Ok(doc
+        .iter()
+        .map(|(key, item)| {
+            let value = match item {
+                Item::Value(value) => {
+                    let mut value = value.clone();
+                    value.decor_mut().clear();
+                    value.to_string()
+                }
+                item => item.to_string().trim().to_string(),
+            };
+            (key.to_string(), value)
+        })
+        .collect())
+}
+
+fn front_matter_changes(from: Option<&str>, to: Option<&str>) -> anyhow::Result<Vec<FieldChange>> {
+    let mut from = front_matter_fields(from)?;
+    let mut to = front_matter_fields(to)?;
+
+    let fields = from
+        .keys()
+        .chain(to.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    Ok(fields
+        .into_iter()
+        .filter_map(|field| {
+            let from = from.remove(&field);
+            let to = to.remove(&field);
+            (from != to).then_some(FieldChange { field, from, to })
+        })
+        .collect())
+}
+
+fn find_page(input_file_id: &str, conn: &mut DbConn) -> QueryResult<Option<Page>> {
+    Page::by_input_file_id(input_file_id).first(conn).optional()
+}
+
+pub fn diff(from: &Revision, to: &Revision, conn: &mut DbConn) -> anyhow::Result<RevisionDiff> {
+    let from_files = InputFileMeta::with_revision(from, conn)?
+        .into_iter()
+        .map(|f| (f.logical_path, f.id))
+        .collect::<BTreeMap<_, _>>();
+    let to_files = InputFileMeta::with_revision(to, conn)?
+        .into_iter()
+        .map(|f| (f.logical_path, f.id))
+        .collect::<BTreeMap<_, _>>();
+
+    let from_routes = Route::with_revision(from, conn)?
+        .into_iter()
+        .map(|r| (r.route, r.input_file_id))
+        .collect::<BTreeMap<_, _>>();
+    let to_routes = Route::with_revision(to, conn)?
+        .into_iter()
+        .map(|r| (r.route, r.input_file_id))
+        .collect::<BTreeMap<_, _>>();
+
+    let added_files = to_files
+        .keys()
+        .filter(|path| !from_files.contains_key(*path))
+        .cloned()
+        .collect();
+    let removed_files = from_files
+        .keys()
+        .filter(|path| !to_files.contains_key(*path))
+        .cloned()
+        .collect();
+    let changed_files = to_files
+        .iter()
+        .filter(|(path, id)| from_files.get(*path).is_some_and(|from_id| from_id != *id))
+        .map(|(path, _)| path.clone())
+        .collect();
+
+    let added_routes = to_routes
+        .keys()
+        .filter(|route| !from_routes.contains_key(*route))
+        .cloned()
+        .collect();
+    let removed_routes = from_routes
+        .keys()
+        .filter(|route| !to_routes.contains_key(*route))
+        .cloned()
+        .collect();
+
+    let mut changed_routes = Vec::new();
+    for (route, to_input_file_id) in &to_routes {
+        let Some(from_input_file_id) = from_routes.get(route) else {
+            continue;
+        };
+        if from_input_file_id == to_input_file_id {
+            continue;
+        }
+
+        let front_matter = match (
+            find_page(from_input_file_id, conn)?,
+            find_page(to_input_file_id, conn)?,
+        ) {
+            (Some(from_page), Some(to_page)) => front_matter_changes(
+                from_page.front_matter.as_deref(),
+                to_page.front_matter.as_deref(),
+            )?,
+            _ => Vec::new(),
+        };
+
+        changed_routes.push(ChangedRoute {
+            route: route.clone(),
+            from_input_file_id: from_input_file_id.clone(),
+            to_input_file_id: to_input_file_id.clone(),
+            front_matter,
+        });
+    }
+
+    Ok(RevisionDiff {
+        from: from.id,
+        to: to.id,
+        added_files,
+        removed_files,
+        changed_files,
+        added_routes,
+        removed_routes,
+        changed_routes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::Site;
+
+    #[test]
+    fn unchanged_front_matter() {
+        let front_matter = r#"title = "Hello World!" # A comment"#;
+
+        assert_eq!(
+            Vec::<FieldChange>::new(),
+            front_matter_changes(Some(front_matter), Some(r#"title =   "Hello World!""#)).unwrap()
+        );
+    }
+
+    #[test]
+    fn changed_front_matter() {
+        let from = r#"
+title = "Hello World!"
+draft = true
+"#;
+        let to = r#"
+title = "Hello!"
+template = "main.hbs"
+"#;
+
+        assert_eq!(
+            vec![
+                FieldChange {
+                    field: String::from("draft"),
+                    from: Some(String::from("true")),
+                    to: None,
+                },
+                FieldChange {
+                    field: String::from("template"),
+                    from: None,
+                    to: Some(String::from(r#""main.hbs""#)),
+                },
+                FieldChange {
+                    field: String::from("title"),
+                    from: Some(String::from(r#""Hello World!""#)),
+                    to: Some(String::from(r#""Hello!""#)),
+                },
+            ],
+            front_matter_changes(Some(from), Some(to)).unwrap()
+        );
+    }
+
+    #[test]
+    fn diff_revisions() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write("content/a.md", "+++\ntitle = \"A\"\n+++\nA\n");
+        site.write("content/b.md", "B\n");
+        site.write("static/s.txt", "S");
+        let from = site.create();
+
+        site.write("content/a.md", "+++\ntitle = \"New A\"\n+++\nA\n");
+        fs::remove_file(site.src().join("content/b.md")).unwrap();
+        site.write("content/c.md", "C\n");
+        let to = site.create();
+
+        let changes = diff(&from, &to, &mut site.conn()).unwrap();
+        assert_eq!((changes.from, changes.to), (from.id, to.id));
+        assert_eq!(changes.added_files, vec!["content/c.md"]);
+        assert_eq!(changes.removed_files, vec!["content/b.md"]);
+        assert_eq!(changes.changed_files, vec!["content/a.md"]);
+        assert_eq!(changes.added_routes, vec!["c.html"]);
+        assert_eq!(changes.removed_routes, vec!["b.html"]);
+        assert_eq!(changes.changed_routes.len(), 1);
+        let changed = &changes.changed_routes[0];
+        assert_eq!(changed.route, "a.html");
+        assert_ne!(changed.from_input_file_id, changed.to_input_file_id);
+        assert_eq!(
+            changed.front_matter,
+            vec![FieldChange {
+                field: String::from("title"),
+                from: Some(String::from(r#""A""#)),
+                to: Some(String::from(r#""New A""#)),
+            }]
+        );
+        assert!(!changes.is_empty());
+        assert!(diff(&to, &to, &mut site.conn()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+//! Syntax highlighting for fenced code blocks.
+//!
+//! Code is highlighted when a revision is published if the site configuration
+//! has a `[highlight]` table:
+//!
+//! ```toml
+//! [highlight]
+//! # One of syntect's default themes. Defaults to "InspiredGitHub".
+//! theme = "base16-ocean.dark"
+//! # "classes" (default) or "inline" styles.
+//! style = "classes"
+//! ```
+//!
+//! With classes, the theme's stylesheet is added to the revision as the
+//! `highlight.css` asset unless the site has its own `assets/highlight.css`.
+//! Templates link to it like any other asset:
+//!
+//! ```html
+//! <link href="highlight.css" rel="stylesheet" />
+//! ```
+//!
+//! Code blocks in an unknown language are not highlighted. A page's
+//! highlighted blocks are cached in the cache directory by the hash of the
+//! page's input file and the options, so unchanged pages are not highlighted
+//! again.
+
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use itertools::Itertools;
+use pulldown_cmark::escape::escape_html;
+use serde_derive::{Deserialize, Serialize};
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    html::{self, ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+/// Name of the generated theme stylesheet asset.
+pub const STYLESHEET: &str = "highlight.css";
+
+const DEFAULT_THEME: &str = "InspiredGitHub";
+
+/// Directory in the cache directory with the highlighted code blocks.
+///
+/// It has a directory for each page's input file hash with a file for each
+/// set of options.
+const CACHE_DIR: &str = "highlight";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// How highlighted code is styled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    /// CSS classes styled by the theme stylesheet.
+    #[default]
+    Classes,
+    /// Inline `style` attributes.
+    Inline,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub theme: Option<String>,
+    #[serde(default)]
+    pub style: Style,
+}
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn themes() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+impl Config {
+    fn theme_name(&self) -> &str {
+        self.theme.as_deref().unwrap_or(DEFAULT_THEME)
+    }
+
+    fn theme(&self) -> anyhow::Result<&'static Theme> {
+        let name = self.theme_name();
+        themes().themes.get(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "unknown highlight theme {name}, expected one of {}",
+                themes().themes.keys().join(", ")
+            )
+        })
+    }
+
+    /// Returns an error if the theme does not exist.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.theme().map(|_| ())
+    }
+
+    /// Returns the theme stylesheet if code is highlighted with classes.
+    pub fn stylesheet(&self) -> anyhow::Result<Option<String>> {
+        match self.style {
+            Style::Classes => Ok(Some(html::css_for_theme_with_class_style(
+                self.theme()?,
+                CLASS_STYLE,
+            )?)),
+            Style::Inline => Ok(None),
+        }
+    }
+}
+
+fn hash_hex(parts: &[&str]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update(b"\0");
+    }
+    format!("{:x}", hasher.finalize().as_bytes().iter().format(""))
+}
+
+/// A highlighted code block.
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+struct Block {
+    /// Hash of the language and code.
+    hash: String,
+    html: String,
+}
+
+/// Highlights the code blocks of a page using the cache directory.
+pub struct Highlighter<'a> {
+    config: &'a Config,
+    cache_path: PathBuf,
+    /// Blocks in the cache from a previous render of the page.
+    cached: Vec<Block>,
+    /// Blocks highlighted in this render of the page in order.
+    blocks: RefCell<Vec<Block>>,
+}
+
+impl<'a> Highlighter<'a> {
+    /// Creates a highlighter for the page with the input file hash.
+    pub fn new(config: &'a Config, cache_dir: &Path, contents_hash: &[u8]) -> anyhow::Result<Self> {
+        let cache_path = cache_dir
+            .join(CACHE_DIR)
+            .join(format!("{:x}", contents_hash.iter().format("")))
+            .join(hash_hex(&[
+                format!("{:?}", config.style).as_str(),
+                config.theme_name(),
+            ]));
+        let cached = match fs::read(&cache_path) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring invalid cache file {}: {e}", cache_path.display());
+                Vec::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            config,
+            cache_path,
+            cached,
+            blocks: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Returns the HTML for a code block or `None` if the language is unknown.
+    ///
+    /// Blocks must be highlighted in the order they are in the page.
+    pub fn highlight(&self, lang: &str, code: &str) -> anyhow::Result<Option<String>> {
+        let Some(syntax) = syntaxes().find_syntax_by_token(lang) else {
+            return Ok(None);
+        };
+
+        let hash = hash_hex(&[lang, code]);
+        let mut blocks = self.blocks.borrow_mut();
+        if let Some(block) = self
+            .cached
+            .get(blocks.len())
+            .filter(|block| block.hash == hash)
+        {
+            blocks.push(Block {
+                hash,
+                html: block.html.clone(),
+            });
+            return Ok(Some(block.html.clone()));
+        }
+
+        let html = match self.config.style {
+            Style::Classes => {
+                let mut generator =
+                    ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes(), CLASS_STYLE);
+                for line in LinesWithEndings::from(code) {
+                    generator.parse_html_for_line_which_includes_newline(line)?;
+                }
+
+                let mut html = String::from("<pre class=\"hl-code\"><code class=\"language-");
+                escape_html(&mut html, lang)?;
+                html.push_str("\">");
+                html.push_str(&generator.finalize());
+                html.push_str("</code></pre>\n");
+                html
+            }
+            Style::Inline => {
+                html::highlighted_html_for_string(code, syntaxes(), syntax, self.config.theme()?)?
+            }
+        };
+
+        blocks.push(Block {
+            hash,
+            html: html.clone(),
+        });
+        Ok(Some(html))
+    }
+
+    /// Caches the blocks highlighted since the highlighter was created if
+    /// they differ from the cached blocks.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let blocks = self.blocks.borrow();
+        if *blocks == self.cached {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.cache_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.cache_path, serde_json::to_vec(&*blocks)?)?;
+        Ok(())
+    }
+}
+
+/// Removes the highlighted code blocks of pages whose input file hash is not
+/// in `live` from the cache directory.
+pub fn remove_stale(cache_dir: &Path, live: &HashSet<String>) -> io::Result<()> {
+    let entries = match fs::read_dir(cache_dir.join(CACHE_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        if !entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| live.contains(name))
+        {
+            tracing::info!(path = %entry.path().display(), "Removed highlighted code blocks.");
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_by_page() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = Config::default();
+        let highlight = |hash: &[u8], blocks: &[(&str, &str)]| {
+            let highlighter = Highlighter::new(&config, cache_dir.path(), hash).unwrap();
+            let html = blocks
+                .iter()
+                .map(|(lang, code)| highlighter.highlight(lang, code).unwrap())
+                .collect::<Vec<_>>();
+            highlighter.save().unwrap();
+            html
+        };
+
+        let html = highlight(b"a", &[("rust", "fn a() {}\n"), ("unknown", "a\n")]);
+        assert!(html[0].as_ref().unwrap().contains("hl-rust"));
+        assert_eq!(html[1], None);
+
+        // The cached blocks are used instead of highlighting the code again.
+        let cache_path = Highlighter::new(&config, cache_dir.path(), b"a")
+            .unwrap()
+            .cache_path;
+        let mut blocks: Vec<Block> =
+            serde_json::from_slice(&fs::read(&cache_path).unwrap()).unwrap();
+        assert_eq!(blocks.len(), 1);
+        blocks[0].html = "cached".to_string();
+        fs::write(&cache_path, serde_json::to_vec(&blocks).unwrap()).unwrap();
+        assert_eq!(
+            highlight(b"a", &[("rust", "fn a() {}\n")]),
+            vec![Some("cached".to_string())]
+        );
+        // Changed blocks are highlighted again.
+        assert!(highlight(b"a", &[("rust", "fn b() {}\n")])[0]
+            .as_ref()
+            .unwrap()
+            .contains("hl-rust"));
+
+        highlight(b"b", &[("rust", "fn b() {}\n")]);
+        remove_stale(cache_dir.path(), &HashSet::from(["62".to_string()])).unwrap();
+        assert!(!cache_dir.path().join(CACHE_DIR).join("61").exists());
+        assert!(cache_dir.path().join(CACHE_DIR).join("62").exists());
+    }
+}
@@ -0,0 +1,432 @@
+//! Checks the internal links of a revision.
+//!
+//! Every published HTML route is rendered and the `href`, `src` and `srcset`
+//! attributes which point inside the base URL are resolved like the server
+//! resolves requests. See [`crate::resolve`]. A link is broken if it does not
+//! resolve, resolves to an unpublished page, or has a fragment which is not
+//! the `id` (or `a` `name`) of an element on the target page.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
+};
+
+use clap::ValueEnum;
+use lol_html::{HtmlRewriter, Settings};
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::{
+    models::{input_file::InputFileMeta, revision::Revision, route::Route, DbConn},
+    publish::{self, Rendered, RenderedRoutes},
+    resolve::resolve,
+    visibility::{self, Unpublished},
+};
+
+/// What publishing does with broken links.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// Print the broken links and publish.
+    Warn,
+    /// Print the broken links and fail without publishing.
+    Error,
+}
+
+/// Elements and attributes with links to check.
+const LINK_SELECTORS: &[(&str, &str)] = &[
+    ("a[href]", "href"),
+    ("area[href]", "href"),
+    ("link[href]", "href"),
+    ("img[src]", "src"),
+    ("script[src]", "src"),
+    ("iframe[src]", "src"),
+    ("audio[src]", "src"),
+    ("video[src]", "src"),
+    ("source[src]", "src"),
+    ("track[src]", "src"),
+    ("embed[src]", "src"),
+];
+
+/// Elements with a `srcset` attribute to check.
+const SRCSET_SELECTORS: &[&str] = &["img[srcset]", "source[srcset]"];
+
+/// Why a link is broken.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// No route or alias exists for the path.
+    Missing(String),
+    /// The target page is not published.
+    Unpublished(visibility::Reason, String),
+    /// The target page has no element with the fragment as its id.
+    MissingFragment(String, String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing(path) => write!(f, "{path} does not exist"),
+            Problem::Unpublished(reason, route) => write!(f, "points to {reason} page {route}"),
+            Problem::MissingFragment(route, fragment) => {
+                write!(f, "{route} has no element with id {fragment}")
+            }
+        }
+    }
+}
+
+/// A broken link in a route.
+#[derive(Debug)]
+pub struct BrokenLink {
+    /// Logical path of the route's input file.
+    pub logical_path: String,
+    pub route: String,
+    /// Attribute with the link.
+    pub attribute: &'static str,
+    pub href: String,
+    pub problem: Problem,
+}
+
+/// Broken links in a revision.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub broken: Vec<BrokenLink>,
+}
+
+impl Report {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.broken.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    /// Lists the broken links grouped by the route they are in.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut by_route = BTreeMap::<(&str, &str), Vec<&BrokenLink>>::new();
+        for link in &self.broken {
+            by_route
+                .entry((&link.route, &link.logical_path))
+                .or_default()
+                .push(link);
+        }
+
+        for ((route, logical_path), links) in &by_route {
+            writeln!(f, "{route} ({logical_path})")?;
+            for link in links {
+                writeln!(
+                    f,
+                    "  {}=\"{}\": {}",
+                    link.attribute, link.href, link.problem
+                )?;
+            }
+        }
+        writeln!(
+            f,
+            "{} broken link(s) in {} route(s)",
+            self.broken.len(),
+            by_route.len()
+        )
+    }
+}
+
+/// Links and element ids in a rendered route.
+#[derive(Debug, Default)]
+struct Scanned {
+    links: Vec<(&'static str, String)>,
+    ids: BTreeSet<String>,
+}
+
+/// Collects the links and element ids in HTML.
+fn scan(html: &[u8]) -> anyhow::Result<Scanned> {
+    let scanned = RefCell::new(Scanned::default());
+
+    let mut handlers = vec![
+        lol_html::element!("[id]", |el| {
+            if let Some(id) = el.get_attribute("id") {
+                scanned.borrow_mut().ids.insert(id);
+            }
+            Ok(())
+        }),
+        lol_html::element!("a[name]", |el| {
+            if let Some(name) = el.get_attribute("name") {
+                scanned.borrow_mut().ids.insert(name);
+            }
+            Ok(())
+        }),
+    ];
+    for &(selector, attribute) in LINK_SELECTORS {
+        let scanned = &scanned;
+        handlers.push(lol_html::element!(selector, move |el| {
+            if let Some(value) = el.get_attribute(attribute) {
+                scanned.borrow_mut().links.push((attribute, value));
+            }
+            Ok(())
+        }));
+    }
+    for &selector in SRCSET_SELECTORS {
+        let scanned = &scanned;
+        handlers.push(lol_html::element!(selector, move |el| {
+            if let Some(srcset) = el.get_attribute("srcset") {
+                scanned
+                    .borrow_mut()
+                    .links
+                    .extend(srcset_urls(&srcset).map(|url| ("srcset", url.to_string())));
+            }
+            Ok(())
+        }));
+    }
+
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: handlers,
+            ..Settings::default()
+        },
+        |_: &[u8]| {},
+    );
+    rewriter.write(html)?;
+    rewriter.end()?;
+
+    Ok(scanned.into_inner())
+}
+
+/// Returns the URLs of the image candidates in a `srcset`.
+fn srcset_urls(srcset: &str) -> impl Iterator<Item = &str> {
+    srcset
+        .split(',')
+        .filter_map(|candidate| candidate.split_whitespace().next())
+}
+
+/// Returns the path relative to the base URL and the fragment of an internal link.
+///
+/// Returns `None` for links outside of the base URL.
+fn internal_link(
+    base_url: &Url,
+    route_abs_url: &Url,
+    href: &str,
+) -> anyhow::Result<Option<(String, Option<String>)>> {
+    let Ok(mut url) = route_abs_url.join(href.trim()) else {
+        return Ok(None);
+    };
+    let fragment = url
+        .fragment()
+        .map(|fragment| percent_decode_str(fragment).decode_utf8())
+        .transpose()?
+        .map(|fragment| fragment.to_string());
+    url.set_fragment(None);
+    url.set_query(None);
+
+    let Some(path) = base_url.make_relative(&url) else {
+        return Ok(None);
+    };
+    if path.starts_with("../") {
+        return Ok(None);
+    }
+
+    Ok(Some((
+        percent_decode_str(&path).decode_utf8()?.to_string(),
+        fragment,
+    )))
+}
+
+/// Returns true if a fragment always refers to a position in a page.
+fn is_builtin_fragment(fragment: &str) -> bool {
+    fragment.is_empty() || fragment.eq_ignore_ascii_case("top")
+}
+
+/// Renders the published HTML routes of the revision and returns the broken
+/// links and the rendered routes, which can be published without rendering
+/// them again.
+pub fn check(
+    rev: &Revision,
+    base_url: &Url,
+    cache_dir: &Path,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> anyhow::Result<(Report, RenderedRoutes)> {
+    let files = InputFileMeta::with_revision(rev, conn)?
+        .into_iter()
+        .map(|f| (f.id.clone(), f))
+        .collect::<BTreeMap<_, _>>();
+    let templates = publish::templates(rev, base_url, unpublished, conn)?;
+
+    let mut rendered = RenderedRoutes::new();
+    let mut scanned = BTreeMap::new();
+    for r in Route::with_revision(rev, conn)? {
+        if unpublished.contains_key(&r.input_file_id) {
+            continue;
+        }
+        let Some(file) = files.get(&r.input_file_id) else {
+            continue;
+        };
+        if !publish::is_rewritten(&file.ty()) {
+            continue;
+        }
+
+        let Some(Rendered::Contents(html)) =
+            publish::render_route(&r, rev, base_url, cache_dir, &templates, unpublished, conn)?
+        else {
+            continue;
+        };
+        scanned.insert(r.route.clone(), (file.logical_path.clone(), scan(&html)?));
+        rendered.insert(r.route, html);
+    }
+
+    let mut report = Report::default();
+    for (route, (logical_path, page)) in &scanned {
+        let route_abs_url = base_url.join(route)?;
+        for (attribute, href) in &page.links {
+            let Some((path, fragment)) = internal_link(base_url, &route_abs_url, href)? else {
+                continue;
+            };
+
+            let problem = match resolve(rev, &path, conn)? {
+                None => Some(Problem::Missing(path)),
+                Some(resolved) => {
+                    let target = resolved.route();
+                    if let Some(reason) = unpublished.get(&target.input_file_id) {
+                        Some(Problem::Unpublished(*reason, target.route.clone()))
+                    } else {
+                        fragment
+                            .filter(|fragment| !is_builtin_fragment(fragment))
+                            .filter(|fragment| {
+                                scanned
+                                    .get(&target.route)
+                                    .is_some_and(|(_, target)| !target.ids.contains(fragment))
+                            })
+                            .map(|fragment| {
+                                Problem::MissingFragment(target.route.clone(), fragment)
+                            })
+                    }
+                }
+            };
+
+            if let Some(problem) = problem {
+                report.broken.push(BrokenLink {
+                    logical_path: logical_path.clone(),
+                    route: route.clone(),
+                    attribute,
+                    href: href.clone(),
+                    problem,
+                });
+            }
+        }
+    }
+
+    Ok((report, rendered))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{testing::Site, visibility::Visibility};
+
+    #[test]
+    fn scan_links_and_ids() {
+        let scanned = scan(
+            br##"<h2 id="intro">Intro</h2><a name="old"></a>
+<a href="setup.html#install">Setup</a>
+<img src="a.png" srcset="a-1x.png 1x, a-2x.png 2x">
+<link href="main.css" rel="stylesheet">"##,
+        )
+        .unwrap();
+        assert_eq!(
+            scanned.ids,
+            BTreeSet::from(["intro".to_string(), "old".to_string()])
+        );
+        assert_eq!(
+            scanned.links,
+            vec![
+                ("href", "setup.html#install".to_string()),
+                ("src", "a.png".to_string()),
+                ("srcset", "a-1x.png".to_string()),
+                ("srcset", "a-2x.png".to_string()),
+                ("href", "main.css".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn internal_links() {
+        let base_url = Url::parse("https://example.com/blog/").unwrap();
+        let route_abs_url = base_url.join("guide/intro.html").unwrap();
+        let link = |href| internal_link(&base_url, &route_abs_url, href).unwrap();
+
+        assert_eq!(
+            link("setup.html#install"),
+            Some(("guide/setup.html".to_string(), Some("install".to_string())))
+        );
+        assert_eq!(
+            link("#top"),
+            Some(("guide/intro.html".to_string(), Some("top".to_string())))
+        );
+        assert_eq!(link("../?page=2"), Some((String::new(), None)));
+        assert_eq!(
+            link("/blog/a%20b.html"),
+            Some(("a b.html".to_string(), None))
+        );
+        assert_eq!(link("/other/"), None);
+        assert_eq!(link("https://example.org/"), None);
+        assert_eq!(link("mailto:a@example.com"), None);
+    }
+
+    #[test]
+    fn check_links() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write("content/about.md", "# Intro\n\nAbout\n");
+        site.write("content/draft.md", "+++\ndraft = true\n+++\nDraft\n");
+        site.write(
+            "content/index.md",
+            "[a](missing.html) [b](draft.html) [c](about.html#nope) \
+            [d](about.html#intro) [e](about#top) [f](/)\n",
+        );
+        let rev = site.create();
+        let mut conn = site.conn();
+        let unpublished = Visibility {
+            include_drafts: false,
+            now: Utc::now().naive_utc(),
+        }
+        .unpublished(&rev, &mut conn)
+        .unwrap();
+
+        let (report, rendered) = check(
+            &rev,
+            &Url::parse("https://example.com/").unwrap(),
+            &site.cache_dir(),
+            &unpublished,
+            &mut conn,
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .broken
+                .iter()
+                .map(|link| (link.href.as_str(), &link.problem))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "missing.html",
+                    &Problem::Missing("missing.html".to_string())
+                ),
+                (
+                    "draft.html",
+                    &Problem::Unpublished(visibility::Reason::Draft, "draft.html".to_string())
+                ),
+                (
+                    "about.html#nope",
+                    &Problem::MissingFragment("about.html".to_string(), "nope".to_string())
+                ),
+            ]
+        );
+        assert!(report.broken.iter().all(|link| link.route == "index.html"));
+
+        // The rendered routes can be published without the unpublished page.
+        assert_eq!(
+            rendered.keys().collect::<Vec<_>>(),
+            vec!["about.html", "index.html"]
+        );
+    }
+}
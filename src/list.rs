@@ -0,0 +1,128 @@
+//! Lists revisions with summary statistics.
+
+use std::{fs, path::Path};
+
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*,
+    sql_types::{Binary, Nullable},
+};
+use itertools::Itertools;
+use serde_derive::Serialize;
+
+use crate::{
+    models::{
+        revision::Revision,
+        revision_file::{self, RevisionFile},
+        route, DbConn, DbId,
+    },
+    schema::{input_files, pages, revision_files, routes},
+};
+
+diesel::sql_function!(fn length(x: Nullable<Binary>) -> Nullable<BigInt>);
+
+#[derive(Debug, Serialize)]
+pub struct RevisionStats {
+    pub id: DbId,
+    pub created_at: NaiveDateTime,
+    pub files: i64,
+    pub routes: i64,
+    pub pages: i64,
+    /// Total size of the file contents stored in the database.
+    pub inline_bytes: u64,
+    /// Total size of the file contents stored in the cache directory.
+    pub cached_bytes: u64,
+}
+
+pub fn revision_stats(
+    rev: &Revision,
+    cache_dir: &Path,
+    conn: &mut DbConn,
+) -> anyhow::Result<RevisionStats> {
+    let files = revision_files::table
+        .filter(revision_file::with_revision_id(rev.id))
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let routes = routes::table
+        .filter(route::with_revision_id(rev.id))
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let pages = pages::table
+        .filter(
+            pages::input_file_id.eq_any(
+                revision_files::table
+                    .filter(revision_file::with_revision_id(rev.id))
+                    .select(revision_files::input_file_id),
+            ),
+        )
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let sizes = RevisionFile::belonging_to(rev)
+        .inner_join(input_files::table)
+        .select((input_files::contents_hash, length(input_files::contents)))
+        .load::<(Vec<u8>, Option<i64>)>(conn)?;
+
+    let mut inline_bytes = 0;
+    let mut cached_bytes = 0;
+    for (contents_hash, len) in sizes {
+        if let Some(len) = len {
+            inline_bytes += u64::try_from(len)?;
+        } else {
+            let content_hash_string = format!("{:x}", contents_hash.iter().format(""));
+            let cache_path = cache_dir.join(content_hash_string);
+            match fs::metadata(&cache_path) {
+                Ok(metadata) => cached_bytes += metadata.len(),
+                Err(e) => {
+                    tracing::warn!(path = %cache_path.display(), "Could not read cache file: {e}");
+                }
+            }
+        }
+    }
+
+    Ok(RevisionStats {
+        id: rev.id,
+        created_at: rev.created_at,
+        files,
+        routes,
+        pages,
+        inline_bytes,
+        cached_bytes,
+    })
+}
+
+pub fn list(cache_dir: &Path, conn: &mut DbConn) -> anyhow::Result<Vec<RevisionStats>> {
+    let revs = Revision::order_by_created_at_desc().load(conn)?;
+
+    revs.iter()
+        .map(|rev| revision_stats(rev, cache_dir, conn))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Site;
+
+    #[test]
+    fn list_revisions_newest_first() {
+        let site = Site::new();
+        site.write("content/a.md", "+++\ntitle = \"A\"\n+++\nA\n");
+        site.write("static/robots.txt", "User-agent: *");
+        let first = site.create();
+
+        site.write("content/b.md", "+++\ntitle = \"B\"\n+++\nB\n");
+        let second = site.create();
+
+        let revs = list(&site.cache_dir(), &mut site.conn()).unwrap();
+        assert_eq!(
+            revs.iter().map(|rev| rev.id).collect::<Vec<_>>(),
+            vec![second.id, first.id]
+        );
+        assert_eq!((revs[0].files, revs[0].routes, revs[0].pages), (3, 3, 2));
+        assert_eq!((revs[1].files, revs[1].routes, revs[1].pages), (2, 2, 1));
+        assert!(revs[0].inline_bytes + revs[0].cached_bytes > revs[1].inline_bytes);
+    }
+}
@@ -1,25 +1,40 @@
-use std::{
-    fs,
-    path::{Path, PathBuf},
-};
+use std::{fs, path::PathBuf, process};
 
-use clap::{command, Parser};
+use clap::Parser;
 
 use cmd::Command;
-use models::DbPool;
-use tracing::info;
 
 mod asset;
 mod build;
 mod cleanup;
 mod cmd;
+mod collection;
 mod content;
+mod context;
 mod delete;
+mod dev;
+mod diff;
+mod highlight;
+mod links;
+mod list;
+mod manifest;
+mod markdown;
 mod models;
+mod prune;
 mod publish;
+mod redirect;
+mod resolve;
 #[allow(clippy::wildcard_imports)]
 mod schema;
+mod serve;
+mod site;
 mod sqlite_mapping;
+mod staging;
+mod taxonomy;
+mod template;
+#[cfg(test)]
+mod testing;
+mod visibility;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -51,13 +66,63 @@ fn main() -> anyhow::Result<()> {
     }
 
     match args.command {
-        Command::Create { src_dir } => cmd::create(&src_dir, &args.cache_dir, pool),
-        Command::Publish {
-            base_url,
-            build_dir,
-            revision,
-        } => cmd::publish(revision, &base_url, &build_dir, &args.cache_dir, pool),
+        Command::Create { src_dir, full } => cmd::create(&src_dir, full, &args.cache_dir, pool),
+        Command::Publish(publish_args) => cmd::publish(&publish_args, &args.cache_dir, pool),
         Command::Delete { revision } => cmd::delete(revision, pool),
         Command::Cleanup => cmd::cleanup(&args.cache_dir, pool),
+        Command::List { json } => cmd::list(json, &args.cache_dir, pool),
+        Command::Diff { from, to, json } => cmd::diff(from, to, json, pool),
+        Command::History {
+            target,
+            build_dir,
+            json,
+        } => cmd::history(target.as_deref(), build_dir.as_deref(), json, pool),
+        Command::Rollback { target, build_dir } => cmd::rollback(
+            target.as_deref(),
+            build_dir.as_deref(),
+            &args.cache_dir,
+            pool,
+        ),
+        Command::Dev {
+            src_dir,
+            addr,
+            hide_unpublished,
+        } => cmd::dev(&src_dir, addr, &args.cache_dir, hide_unpublished, pool),
+        Command::Serve {
+            revision,
+            addr,
+            hide_unpublished,
+        } => cmd::serve(revision, addr, &args.cache_dir, hide_unpublished, pool),
+        Command::Due {
+            build_dir,
+            revision,
+            since,
+            now,
+            include_drafts,
+        } => {
+            if cmd::due(&build_dir, revision, since, now, include_drafts, pool)? {
+                process::exit(2);
+            }
+            Ok(())
+        }
+        Command::CheckLinks {
+            revision,
+            base_url,
+            include_drafts,
+            now,
+        } => {
+            if !cmd::check_links(
+                revision,
+                base_url,
+                include_drafts,
+                now,
+                &args.cache_dir,
+                pool,
+            )? {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Target { command } => cmd::target(command, pool),
     }
 }
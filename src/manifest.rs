@@ -0,0 +1,244 @@
+//! Records what was published into a build directory.
+//!
+//! The manifest allows a later publish to only write the routes which have
+//! changed since the last publish into the same directory. Manifests are kept
+//! in the cache directory, keyed by the build directory's path, so they are
+//! not deployed with the build.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+use serde_derive::{Deserialize, Serialize};
+use url::Url;
+
+use crate::models::{input_file::Ty, DbId};
+
+/// Directory in the cache directory with the manifests.
+const CACHE_DIR: &str = "manifests";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub revision_id: DbId,
+    pub base_url: String,
+    /// Hash of the inputs which may affect every rewritten HTML route.
+    ///
+    /// Includes the base URL, the set of routes, the input files of generated
+    /// and asset routes, the redirects, the templates, and other dependencies
+    /// such as page tags. Pages and static files are only linked to by route so
+    /// their contents do not affect other routes.
+    pub shared_hash: String,
+    /// Map of routes to input file IDs.
+    ///
+    /// Redirect outputs are mapped to a hash of their contents instead.
+    pub routes: BTreeMap<String, String>,
+}
+
+/// Returns the path of the manifest for the build directory.
+fn path(cache_dir: &Path, dest: &Path) -> io::Result<PathBuf> {
+    let dest = match dest.canonicalize() {
+        Ok(dest) => dest,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => std::path::absolute(dest)?,
+        Err(e) => return Err(e),
+    };
+    let hash = blake3::hash(dest.as_os_str().as_encoded_bytes());
+    Ok(cache_dir
+        .join(CACHE_DIR)
+        .join(format!("{:x}.json", hash.as_bytes().iter().format(""))))
+}
+
+impl Manifest {
+    /// Creates a manifest.
+    ///
+    /// `files` is a map of input file IDs to their type for every file in the revision.
+    /// `dependencies` are other inputs which may affect every rewritten HTML route.
+    pub fn new(
+        revision_id: DbId,
+        base_url: &Url,
+        routes: BTreeMap<String, String>,
+        files: &BTreeMap<&str, Ty<'_>>,
+        dependencies: &[String],
+    ) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(base_url.as_str().as_bytes());
+        hasher.update(b"\n");
+
+        for (route, input_file_id) in &routes {
+            hasher.update(route.as_bytes());
+            hasher.update(b"\n");
+            if !matches!(
+                files.get(input_file_id.as_str()),
+                Some(Ty::Content(_) | Ty::Static(_))
+            ) {
+                hasher.update(input_file_id.as_bytes());
+            }
+            hasher.update(b"\n");
+        }
+
+        for (input_file_id, _) in files.iter().filter(|(_, ty)| matches!(ty, Ty::Template(_))) {
+            hasher.update(input_file_id.as_bytes());
+            hasher.update(b"\n");
+        }
+
+        for dependency in dependencies {
+            hasher.update(dependency.as_bytes());
+            hasher.update(b"\n");
+        }
+
+        let shared_hash = format!("{:x}", hasher.finalize().as_bytes().iter().format(""));
+
+        Self {
+            revision_id,
+            base_url: base_url.to_string(),
+            shared_hash,
+            routes,
+        }
+    }
+
+    /// Reads the manifest for the build directory if it exists.
+    pub fn read(cache_dir: &Path, dest: &Path) -> anyhow::Result<Option<Self>> {
+        match fs::read(path(cache_dir, dest)?) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Removes the manifest for the build directory if it exists.
+    ///
+    /// Should be called before modifying the build directory so an incomplete
+    /// publish is not mistaken for a complete one.
+    pub fn remove(cache_dir: &Path, dest: &Path) -> io::Result<()> {
+        match fs::remove_file(path(cache_dir, dest)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn write(&self, cache_dir: &Path, dest: &Path) -> anyhow::Result<()> {
+        let path = path(cache_dir, dest)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Returns true if the route's output in the build directory is unchanged
+    /// compared to the output for the `current` manifest.
+    pub fn is_unchanged(&self, current: &Self, route: &str, is_rewritten: bool) -> bool {
+        self.routes.get(route) == current.routes.get(route)
+            && (!is_rewritten || self.shared_hash == current.shared_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(base_url: &str, routes: &[(&str, &str)], files: &[(&str, Ty<'_>)]) -> Manifest {
+        Manifest::new(
+            1,
+            &Url::parse(base_url).unwrap(),
+            routes
+                .iter()
+                .map(|(route, id)| ((*route).to_string(), (*id).to_string()))
+                .collect(),
+            &files.iter().cloned().collect(),
+            &[],
+        )
+    }
+
+    #[test]
+    fn unchanged_routes() {
+        let files = [
+            ("a1,content/a.md", Ty::Content("a.md")),
+            ("a2,content/a.md", Ty::Content("a.md")),
+            ("s1,static/s.txt", Ty::Static("s.txt")),
+            ("s2,static/s.txt", Ty::Static("s.txt")),
+            ("t1,templates/t.hbs", Ty::Template("t.hbs")),
+        ];
+        let previous = manifest(
+            "https://example.com/",
+            &[("a.html", "a1,content/a.md"), ("s.txt", "s1,static/s.txt")],
+            &files[..],
+        );
+
+        let same = manifest(
+            "https://example.com/",
+            &[("a.html", "a1,content/a.md"), ("s.txt", "s1,static/s.txt")],
+            &files[..],
+        );
+        assert!(previous.is_unchanged(&same, "a.html", true));
+        assert!(previous.is_unchanged(&same, "s.txt", false));
+
+        // A changed page only changes its own route.
+        let page_changed = manifest(
+            "https://example.com/",
+            &[("a.html", "a2,content/a.md"), ("s.txt", "s1,static/s.txt")],
+            &files[..],
+        );
+        assert!(!previous.is_unchanged(&page_changed, "a.html", true));
+        assert!(previous.is_unchanged(&page_changed, "s.txt", false));
+        assert_eq!(previous.shared_hash, page_changed.shared_hash);
+
+        // A changed static file only changes its own route.
+        let static_changed = manifest(
+            "https://example.com/",
+            &[("a.html", "a1,content/a.md"), ("s.txt", "s2,static/s.txt")],
+            &files[..],
+        );
+        assert!(previous.is_unchanged(&static_changed, "a.html", true));
+        assert!(!previous.is_unchanged(&static_changed, "s.txt", false));
+
+        // A changed base URL or template changes every rewritten route.
+        let base_url_changed = manifest(
+            "https://example.org/",
+            &[("a.html", "a1,content/a.md"), ("s.txt", "s1,static/s.txt")],
+            &files[..],
+        );
+        assert!(!previous.is_unchanged(&base_url_changed, "a.html", true));
+        assert!(previous.is_unchanged(&base_url_changed, "s.txt", false));
+
+        let template_changed = manifest(
+            "https://example.com/",
+            &[("a.html", "a1,content/a.md"), ("s.txt", "s1,static/s.txt")],
+            &files[..4],
+        );
+        assert!(!previous.is_unchanged(&template_changed, "a.html", true));
+
+        // A new route is changed.
+        let route_added = manifest(
+            "https://example.com/",
+            &[
+                ("a.html", "a1,content/a.md"),
+                ("b.html", "a1,content/a.md"),
+                ("s.txt", "s1,static/s.txt"),
+            ],
+            &files[..],
+        );
+        assert!(!previous.is_unchanged(&route_added, "b.html", false));
+    }
+
+    #[test]
+    fn stored_outside_build_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let dest = dir.path().join("build");
+        fs::create_dir_all(&dest).unwrap();
+
+        let manifest = manifest("https://example.com/", &[("a.html", "a1")], &[]);
+        assert_eq!(Manifest::read(&cache_dir, &dest).unwrap(), None);
+        manifest.write(&cache_dir, &dest).unwrap();
+        assert_eq!(Manifest::read(&cache_dir, &dest).unwrap(), Some(manifest));
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 0);
+
+        Manifest::remove(&cache_dir, &dest).unwrap();
+        assert_eq!(Manifest::read(&cache_dir, &dest).unwrap(), None);
+    }
+}
@@ -0,0 +1,445 @@
+//! Renders page content from Markdown.
+//!
+//! Extensions are enabled with a `[markdown]` table in the site configuration
+//! and can be overridden for a page with a `[markdown]` table in its front
+//! matter:
+//!
+//! ```toml
+//! [markdown]
+//! tables = true
+//! footnotes = true
+//! strikethrough = true
+//! tasklists = true
+//! smart_punctuation = true
+//! heading_attributes = true
+//! # Add a link to itself at the end of each heading.
+//! heading_links = true
+//! ```
+//!
+//! Every extension is disabled unless enabled.
+//!
+//! Headings are given an `id` from their text unless one is set with
+//! `heading_attributes`. Repeated ids have `-1`, `-2`, ... appended.
+
+use anyhow::Context;
+use std::collections::HashSet;
+
+use pulldown_cmark::{
+    escape::{escape_href, escape_html},
+    html, CodeBlockKind, Event, Options, Parser, Tag,
+};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{content, highlight::Highlighter, taxonomy};
+
+/// Key of the extensions table in the site configuration and front matter.
+pub const KEY: &str = "markdown";
+
+/// Markdown extensions which are enabled, disabled or unset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Extensions {
+    pub tables: Option<bool>,
+    pub footnotes: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub tasklists: Option<bool>,
+    pub smart_punctuation: Option<bool>,
+    pub heading_attributes: Option<bool>,
+    pub heading_links: Option<bool>,
+}
+
+impl Extensions {
+    /// Returns the page's extensions from its front matter.
+    pub fn from_front_matter(front_matter: &str, logical_path: &str) -> anyhow::Result<Self> {
+        let Some(value) = content::front_matter_json(front_matter)?.remove(KEY) else {
+            return Ok(Self::default());
+        };
+
+        serde_json::from_value(value).with_context(|| format!("invalid {KEY} in {logical_path}"))
+    }
+
+    /// Returns the extensions with unset values taken from `other`.
+    #[must_use]
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            tables: self.tables.or(other.tables),
+            footnotes: self.footnotes.or(other.footnotes),
+            strikethrough: self.strikethrough.or(other.strikethrough),
+            tasklists: self.tasklists.or(other.tasklists),
+            smart_punctuation: self.smart_punctuation.or(other.smart_punctuation),
+            heading_attributes: self.heading_attributes.or(other.heading_attributes),
+            heading_links: self.heading_links.or(other.heading_links),
+        }
+    }
+
+    /// Returns the parser options for the enabled extensions.
+    #[must_use]
+    pub fn options(&self) -> Options {
+        let mut options = Options::empty();
+        for (is_enabled, option) in [
+            (self.tables, Options::ENABLE_TABLES),
+            (self.footnotes, Options::ENABLE_FOOTNOTES),
+            (self.strikethrough, Options::ENABLE_STRIKETHROUGH),
+            (self.tasklists, Options::ENABLE_TASKLISTS),
+            (self.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+            (self.heading_attributes, Options::ENABLE_HEADING_ATTRIBUTES),
+        ] {
+            options.set(option, is_enabled.unwrap_or_default());
+        }
+        options
+    }
+}
+
+/// A heading in rendered Markdown.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Heading {
+    pub level: usize,
+    pub anchor: String,
+    pub title: String,
+}
+
+/// Returns the headings nested under their parent headings.
+///
+/// Each entry has the heading's `level`, `id`, `title` and `children`.
+pub fn toc(headings: &[Heading]) -> Value {
+    let mut entries = Vec::new();
+    let mut i = 0;
+    while let Some(heading) = headings.get(i) {
+        let end = headings[i + 1..]
+            .iter()
+            .position(|next| next.level <= heading.level)
+            .map_or(headings.len(), |position| i + 1 + position);
+        entries.push(json!({
+            "level": heading.level,
+            "id": heading.anchor,
+            "title": heading.title,
+            "children": toc(&headings[i + 1..end]),
+        }));
+        i = end;
+    }
+    Value::Array(entries)
+}
+
+/// Gives every heading an id and returns the headings.
+fn add_heading_ids<'a>(events: Vec<Event<'a>>, links: bool) -> (Vec<Event<'a>>, Vec<Heading>) {
+    let mut output = Vec::with_capacity(events.len());
+    let mut headings = Vec::new();
+    let mut anchors = HashSet::new();
+
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::Heading(level, id, classes)) = event else {
+            output.push(event);
+            continue;
+        };
+
+        let mut inner = Vec::new();
+        let mut title = String::new();
+        for event in events.by_ref() {
+            match &event {
+                Event::End(Tag::Heading(..)) => break,
+                Event::Text(text) | Event::Code(text) => title.push_str(text),
+                _ => {}
+            }
+            inner.push(event);
+        }
+
+        let anchor = if let Some(id) = id {
+            id.to_string()
+        } else {
+            let mut slug = taxonomy::slug(&title);
+            if slug.is_empty() {
+                slug.push_str("section");
+            }
+            let mut anchor = slug.clone();
+            let mut n = 1;
+            while anchors.contains(&anchor) {
+                anchor = format!("{slug}-{n}");
+                n += 1;
+            }
+            anchor
+        };
+        anchors.insert(anchor.clone());
+
+        let mut open = format!("<{level} id=\"");
+        escape_html(&mut open, &anchor).expect("writing to a string should not fail");
+        open.push('"');
+        if !classes.is_empty() {
+            open.push_str(" class=\"");
+            escape_html(&mut open, &classes.join(" "))
+                .expect("writing to a string should not fail");
+            open.push('"');
+        }
+        open.push('>');
+
+        output.push(Event::Html(open.into()));
+        output.extend(inner);
+        if links {
+            let mut link = String::from(" <a class=\"heading-link\" href=\"#");
+            escape_href(&mut link, &anchor).expect("writing to a string should not fail");
+            link.push_str("\" aria-hidden=\"true\">#</a>");
+            output.push(Event::Html(link.into()));
+        }
+        output.push(Event::End(Tag::Heading(level, id, classes)));
+
+        headings.push(Heading {
+            level: level as usize,
+            anchor,
+            title,
+        });
+    }
+
+    (output, headings)
+}
+
+/// Rendered Markdown.
+pub struct Rendered {
+    pub html: String,
+    pub headings: Vec<Heading>,
+}
+
+/// Renders Markdown as HTML.
+///
+/// Fenced code blocks are highlighted with the `highlighter` if given.
+pub fn render(
+    contents: &str,
+    extensions: &Extensions,
+    highlighter: Option<&Highlighter<'_>>,
+) -> anyhow::Result<Rendered> {
+    let parser = Parser::new_ext(contents, extensions.options());
+
+    let mut events = Vec::new();
+    let mut code_block = None;
+    for event in parser {
+        match (&mut code_block, event) {
+            (None, Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))))
+                if highlighter.is_some() =>
+            {
+                code_block = Some((lang, String::new()));
+            }
+            (Some((_, code)), Event::Text(text)) => code.push_str(&text),
+            (Some(_), Event::End(Tag::CodeBlock(kind))) => {
+                let Some((lang, code)) = code_block.take() else {
+                    unreachable!()
+                };
+                // The info string may have other words after the language.
+                let token = lang.split([' ', ',']).next().unwrap_or_default();
+                if let Some(html) = highlighter
+                    .map(|highlighter| highlighter.highlight(token, &code))
+                    .transpose()?
+                    .flatten()
+                {
+                    events.push(Event::Html(html.into()));
+                } else {
+                    events.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))));
+                    events.push(Event::Text(code.into()));
+                    events.push(Event::End(Tag::CodeBlock(kind)));
+                }
+            }
+            (_, event) => events.push(event),
+        }
+    }
+
+    if let Some(highlighter) = highlighter {
+        highlighter.save()?;
+    }
+
+    let (events, headings) = add_heading_ids(events, extensions.heading_links.unwrap_or_default());
+
+    let mut html = String::new();
+    html::push_html(&mut html, events.into_iter());
+    Ok(Rendered { html, headings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlight;
+
+    fn enabled(f: impl FnOnce(&mut Extensions)) -> Extensions {
+        let mut extensions = Extensions::default();
+        f(&mut extensions);
+        extensions
+    }
+
+    #[test]
+    fn extensions_disabled_by_default() {
+        let html = render("| a |\n|---|\n| b |\n\n~~c~~", &Extensions::default(), None)
+            .unwrap()
+            .html;
+        assert_eq!(html, "<p>| a |\n|---|\n| b |</p>\n<p>~~c~~</p>\n");
+    }
+
+    #[test]
+    fn tables() {
+        let html = render(
+            "| a |\n|---|\n| b |\n",
+            &enabled(|e| e.tables = Some(true)),
+            None,
+        )
+        .unwrap()
+        .html;
+        assert_eq!(
+            html,
+            "<table><thead><tr><th>a</th></tr></thead><tbody>\n<tr><td>b</td></tr>\n</tbody></table>\n"
+        );
+    }
+
+    #[test]
+    fn footnotes() {
+        let html = render(
+            "Text[^1]\n\n[^1]: Note\n",
+            &enabled(|e| e.footnotes = Some(true)),
+            None,
+        )
+        .unwrap()
+        .html;
+        assert_eq!(
+            html,
+            "<p>Text<sup class=\"footnote-reference\"><a href=\"#1\">1</a></sup></p>\n\
+            <div class=\"footnote-definition\" id=\"1\"><sup class=\"footnote-definition-label\">1</sup>\n\
+            <p>Note</p>\n</div>\n"
+        );
+    }
+
+    #[test]
+    fn strikethrough() {
+        let html = render("~~a~~", &enabled(|e| e.strikethrough = Some(true)), None)
+            .unwrap()
+            .html;
+        assert_eq!(html, "<p><del>a</del></p>\n");
+    }
+
+    #[test]
+    fn tasklists() {
+        let html = render(
+            "- [x] a\n- [ ] b\n",
+            &enabled(|e| e.tasklists = Some(true)),
+            None,
+        )
+        .unwrap()
+        .html;
+        assert_eq!(
+            html,
+            "<ul>\n<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\na</li>\n\
+            <li><input disabled=\"\" type=\"checkbox\"/>\nb</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn smart_punctuation() {
+        let html = render(
+            "\"a\" -- b...",
+            &enabled(|e| e.smart_punctuation = Some(true)),
+            None,
+        )
+        .unwrap()
+        .html;
+        assert_eq!(html, "<p>“a” – b…</p>\n");
+    }
+
+    #[test]
+    fn heading_attributes() {
+        let html = render(
+            "# a {#b .c}",
+            &enabled(|e| e.heading_attributes = Some(true)),
+            None,
+        )
+        .unwrap()
+        .html;
+        assert_eq!(html, "<h1 id=\"b\" class=\"c\">a</h1>\n");
+    }
+
+    #[test]
+    fn highlighted_code_blocks() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = highlight::Config::default();
+        let highlighter = || Highlighter::new(&config, cache_dir.path(), b"page").unwrap();
+
+        let contents = "```rust,ignore\nfn main() {}\n```\n\n```unknown\n<a>\n```\n";
+        let html = render(contents, &Extensions::default(), Some(&highlighter()))
+            .unwrap()
+            .html;
+        assert!(html.starts_with(
+            "<pre class=\"hl-code\"><code class=\"language-rust\"><span class=\"hl-source hl-rust\">"
+        ));
+        assert!(html.ends_with("<pre><code class=\"language-unknown\">&lt;a&gt;\n</code></pre>\n"));
+
+        // Cached blocks are reused.
+        assert_eq!(
+            render(contents, &Extensions::default(), Some(&highlighter()))
+                .unwrap()
+                .html,
+            html
+        );
+    }
+
+    #[test]
+    fn heading_ids_and_toc() {
+        let rendered = render(
+            "# Intro\n## Setup `proj`\n## Setup proj\n# Intro\n### Deep {#custom}\n",
+            &enabled(|e| {
+                e.heading_attributes = Some(true);
+                e.heading_links = Some(true);
+            }),
+            None,
+        )
+        .unwrap();
+        assert!(rendered.html.starts_with(
+            "<h1 id=\"intro\">Intro <a class=\"heading-link\" href=\"#intro\" aria-hidden=\"true\">#</a></h1>\n"
+        ));
+        assert_eq!(
+            rendered
+                .headings
+                .iter()
+                .map(|heading| heading.anchor.as_str())
+                .collect::<Vec<_>>(),
+            ["intro", "setup-proj", "setup-proj-1", "intro-1", "custom"]
+        );
+
+        assert_eq!(
+            toc(&rendered.headings),
+            json!([
+                {
+                    "level": 1,
+                    "id": "intro",
+                    "title": "Intro",
+                    "children": [
+                        { "level": 2, "id": "setup-proj", "title": "Setup proj", "children": [] },
+                        { "level": 2, "id": "setup-proj-1", "title": "Setup proj", "children": [] },
+                    ],
+                },
+                {
+                    "level": 1,
+                    "id": "intro-1",
+                    "title": "Intro",
+                    "children": [
+                        { "level": 3, "id": "custom", "title": "Deep", "children": [] },
+                    ],
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn page_overrides_site() {
+        let site = enabled(|e| {
+            e.tables = Some(true);
+            e.footnotes = Some(true);
+        });
+        let page = Extensions::from_front_matter(
+            "title = \"a\"\n[markdown]\ntables = false\nstrikethrough = true\n",
+            "content/a.md",
+        )
+        .unwrap();
+
+        let options = page.or(site).options();
+        assert!(!options.contains(Options::ENABLE_TABLES));
+        assert!(options.contains(Options::ENABLE_FOOTNOTES));
+        assert!(options.contains(Options::ENABLE_STRIKETHROUGH));
+
+        assert!(Extensions::from_front_matter("[markdown]\ntable = true\n", "a.md").is_err());
+        assert!(Extensions::from_front_matter("[markdown]\ntables = 1\n", "a.md").is_err());
+    }
+}
@@ -9,16 +9,22 @@ use diesel::{
 };
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
+pub mod file_stat;
 pub mod input_file;
 pub mod page;
+pub mod page_alias;
+pub mod page_heading;
+pub mod page_tag;
+pub mod publication;
 pub mod revision;
 pub mod revision_file;
 pub mod route;
+pub mod target;
 
 pub type DbId = i64;
 pub type DbConn = SqliteConnection;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DbPool {
     inner: Pool<ConnectionManager<SqliteConnection>>,
 }
@@ -34,7 +40,7 @@ const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
 pub fn run_migrations<T, DB>(
     conn: &mut T,
-) -> Result<Vec<MigrationVersion>, Box<dyn Error + Send + Sync + 'static>>
+) -> Result<Vec<MigrationVersion<'_>>, Box<dyn Error + Send + Sync + 'static>>
 where
     T: MigrationHarness<DB>,
     DB: Backend,
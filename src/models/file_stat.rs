@@ -0,0 +1,77 @@
+use diesel::{
+    backend::Backend,
+    helper_types::{AsSelect, Select},
+    prelude::*,
+};
+
+use crate::{
+    models::{
+        input_file::{InputFile, InputFileMeta},
+        DbConn,
+    },
+    schema::{file_stats, input_files},
+};
+
+/// File system metadata of a source file when it was last hashed.
+///
+/// Used to skip reading and hashing files which have not changed since the
+/// last revision was created.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(InputFile))]
+#[diesel(primary_key(disk_path))]
+pub struct FileStat {
+    pub disk_path: String,
+    pub size: i64,
+    pub modified: i64,
+    pub inode: Option<i64>,
+    pub input_file_id: String,
+    pub recorded_at: i64,
+}
+
+type All<Db> = Select<file_stats::table, AsSelect<FileStat, Db>>;
+
+impl FileStat {
+    #[inline]
+    #[must_use]
+    pub fn all<Db>() -> All<Db>
+    where
+        Db: Backend,
+    {
+        file_stats::table.select(Self::as_select())
+    }
+
+    /// Loads every file stat with its input file and whether the input file
+    /// contents are stored in the database.
+    #[inline]
+    pub fn with_input_files(conn: &mut DbConn) -> QueryResult<Vec<(Self, InputFileMeta, bool)>> {
+        file_stats::table
+            .inner_join(input_files::table)
+            .select((
+                Self::as_select(),
+                InputFileMeta::as_select(),
+                input_files::contents.is_not_null(),
+            ))
+            .load(conn)
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Insertable)]
+#[diesel(table_name = file_stats)]
+pub struct NewFileStat<'a> {
+    pub disk_path: &'a str,
+    pub size: i64,
+    pub modified: i64,
+    pub inode: Option<i64>,
+    pub input_file_id: &'a str,
+    pub recorded_at: i64,
+}
+
+impl<'a> NewFileStat<'a> {
+    /// Inserts or replaces the file stat for the disk path.
+    pub fn upsert(&self, conn: &mut DbConn) -> QueryResult<usize> {
+        diesel::replace_into(file_stats::table)
+            .values(self)
+            .execute(conn)
+    }
+}
@@ -23,7 +23,7 @@ use crate::{
 
 use super::{revision::Revision, revision_file::RevisionFile};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Ty<'a> {
     Asset(&'a str),
     Static(&'a str),
@@ -165,12 +165,27 @@ impl InputFile {
     }
 
     #[inline]
-    pub fn template(rev: &Revision, name: &str, conn: &mut DbConn) -> QueryResult<Self> {
+    pub fn templates(rev: &Revision, conn: &mut DbConn) -> QueryResult<Vec<Self>> {
         RevisionFile::belonging_to(rev)
             .inner_join(input_files::table)
-            .filter(with_logical_path(format!("templates/{name}")))
+            .filter(input_files::logical_path.like("templates/%"))
             .select(Self::as_select())
-            .get_result(conn)
+            .load(conn)
+    }
+
+    /// The file in the revision with the logical path if it exists.
+    #[inline]
+    pub fn find(
+        rev: &Revision,
+        logical_path: &str,
+        conn: &mut DbConn,
+    ) -> QueryResult<Option<Self>> {
+        RevisionFile::belonging_to(rev)
+            .inner_join(input_files::table)
+            .filter(with_logical_path(logical_path))
+            .select(Self::as_select())
+            .first(conn)
+            .optional()
     }
 
     #[inline]
@@ -220,6 +235,7 @@ impl InputFile {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Clone, PartialEq, Eq)]
 pub struct Id(pub String);
 
@@ -311,4 +327,8 @@ impl InputFileMeta {
             .select(Self::as_select())
             .load(conn)
     }
+    #[must_use]
+    pub fn ty(&self) -> Ty<'_> {
+        ty(&self.logical_path)
+    }
 }
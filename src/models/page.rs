@@ -7,7 +7,10 @@ use diesel::{
     sql_types::Text,
 };
 
-use crate::{models::DbConn, schema::pages};
+use crate::{
+    models::{revision::Revision, revision_file::RevisionFile, DbConn},
+    schema::{input_files, pages},
+};
 
 #[derive(Debug, PartialEq, Queryable, Selectable, Identifiable)]
 #[diesel(primary_key(input_file_id))]
@@ -59,6 +62,29 @@ impl Page {
     {
         Self::all().filter(with_input_file_id(id))
     }
+
+    #[inline]
+    pub fn with_revision(rev: &Revision, conn: &mut DbConn) -> QueryResult<Vec<Self>> {
+        RevisionFile::belonging_to(rev)
+            .inner_join(input_files::table.inner_join(pages::table))
+            .select(Self::as_select())
+            .load(conn)
+    }
+
+    /// Returns the `_index.md` pages of content sections in the revision with their logical paths.
+    #[inline]
+    pub fn section_indexes(rev: &Revision, conn: &mut DbConn) -> QueryResult<Vec<(String, Self)>> {
+        Ok(RevisionFile::belonging_to(rev)
+            .inner_join(input_files::table.inner_join(pages::table))
+            .filter(input_files::logical_path.like("content/%_index.md"))
+            .select((input_files::logical_path, Self::as_select()))
+            .load::<(String, Self)>(conn)?
+            .into_iter()
+            .filter(|(logical_path, _)| {
+                logical_path == "content/_index.md" || logical_path.ends_with("/_index.md")
+            })
+            .collect())
+    }
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -0,0 +1,110 @@
+use diesel::{
+    backend::Backend,
+    expression::AsExpression,
+    helper_types::{AsSelect, Filter, Select},
+    prelude::*,
+    sql_types::Text,
+};
+
+use crate::{
+    models::{input_file::InputFile, revision::Revision, revision_file::RevisionFile, DbConn},
+    schema::{input_files, page_aliases},
+};
+
+/// An alias from a page's front matter.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(InputFile))]
+#[diesel(table_name = page_aliases)]
+#[diesel(primary_key(input_file_id, alias))]
+pub struct PageAlias {
+    pub input_file_id: String,
+    pub alias: String,
+}
+
+type WithInputFileId<T> = diesel::dsl::Eq<page_aliases::input_file_id, T>;
+type WithAlias<T> = diesel::dsl::Eq<page_aliases::alias, T>;
+
+#[inline]
+#[must_use]
+pub fn with_input_file_id<T>(id: T) -> WithInputFileId<T>
+where
+    T: AsExpression<Text>,
+{
+    page_aliases::input_file_id.eq(id)
+}
+
+#[inline]
+#[must_use]
+pub fn with_alias<T>(alias: T) -> WithAlias<T>
+where
+    T: AsExpression<Text>,
+{
+    page_aliases::alias.eq(alias)
+}
+
+type All<Db> = Select<page_aliases::table, AsSelect<PageAlias, Db>>;
+type ByInputFileId<T, Db> = Filter<All<Db>, WithInputFileId<T>>;
+
+impl PageAlias {
+    #[inline]
+    #[must_use]
+    pub fn all<Db>() -> All<Db>
+    where
+        Db: Backend,
+    {
+        page_aliases::table.select(Self::as_select())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn by_input_file_id<Db>(id: &str) -> ByInputFileId<&'_ str, Db>
+    where
+        Db: Backend,
+    {
+        Self::all().filter(with_input_file_id(id))
+    }
+
+    /// All aliases of pages in the revision.
+    #[inline]
+    pub fn with_revision(rev: &Revision, conn: &mut DbConn) -> QueryResult<Vec<Self>> {
+        RevisionFile::belonging_to(rev)
+            .inner_join(input_files::table.inner_join(page_aliases::table))
+            .select(Self::as_select())
+            .order((page_aliases::alias, page_aliases::input_file_id))
+            .load(conn)
+    }
+
+    /// The alias in the revision if a page declares it.
+    #[inline]
+    pub fn find(rev: &Revision, alias: &str, conn: &mut DbConn) -> QueryResult<Option<Self>> {
+        RevisionFile::belonging_to(rev)
+            .inner_join(input_files::table.inner_join(page_aliases::table))
+            .filter(with_alias(alias))
+            .select(Self::as_select())
+            .first(conn)
+            .optional()
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Insertable)]
+#[diesel(table_name = page_aliases)]
+pub struct NewPageAlias<'a> {
+    pub input_file_id: &'a str,
+    pub alias: &'a str,
+}
+
+impl<'a> NewPageAlias<'a> {
+    pub fn new(input_file_id: &'a str, alias: &'a str) -> Self {
+        Self {
+            input_file_id,
+            alias,
+        }
+    }
+
+    pub fn create(&self, conn: &mut DbConn) -> QueryResult<usize> {
+        diesel::insert_or_ignore_into(page_aliases::table)
+            .values(self)
+            .execute(conn)
+    }
+}
@@ -0,0 +1,72 @@
+use diesel::{
+    backend::Backend,
+    helper_types::{AsSelect, Select},
+    prelude::*,
+};
+
+use crate::{
+    models::{input_file::InputFile, revision::Revision, DbConn, DbId},
+    schema::page_headings,
+};
+
+/// A heading in a page's content.
+///
+/// Headings depend on the Markdown extensions of the site configuration so
+/// they are stored for each revision.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(Revision))]
+#[diesel(belongs_to(InputFile))]
+#[diesel(table_name = page_headings)]
+#[diesel(primary_key(revision_id, input_file_id, position))]
+pub struct PageHeading {
+    pub revision_id: DbId,
+    pub input_file_id: String,
+    /// Index of the heading in the page.
+    pub position: i64,
+    pub level: i64,
+    /// The heading's `id` in the rendered page.
+    pub anchor: String,
+    pub title: String,
+}
+
+type All<Db> = Select<page_headings::table, AsSelect<PageHeading, Db>>;
+
+impl PageHeading {
+    #[inline]
+    #[must_use]
+    pub fn all<Db>() -> All<Db>
+    where
+        Db: Backend,
+    {
+        page_headings::table.select(Self::as_select())
+    }
+
+    /// All headings of pages in the revision.
+    #[inline]
+    pub fn with_revision(rev: &Revision, conn: &mut DbConn) -> QueryResult<Vec<Self>> {
+        PageHeading::belonging_to(rev)
+            .select(Self::as_select())
+            .order((page_headings::input_file_id, page_headings::position))
+            .load(conn)
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Insertable)]
+#[diesel(table_name = page_headings)]
+pub struct NewPageHeading<'a> {
+    pub revision_id: DbId,
+    pub input_file_id: &'a str,
+    pub position: i64,
+    pub level: i64,
+    pub anchor: &'a str,
+    pub title: &'a str,
+}
+
+impl<'a> NewPageHeading<'a> {
+    pub fn create(&self, conn: &mut DbConn) -> QueryResult<usize> {
+        diesel::insert_or_ignore_into(page_headings::table)
+            .values(self)
+            .execute(conn)
+    }
+}
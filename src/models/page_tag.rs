@@ -0,0 +1,113 @@
+use diesel::{
+    backend::Backend,
+    expression::AsExpression,
+    helper_types::{AsSelect, Filter, Select},
+    prelude::*,
+    sql_types::Text,
+};
+
+use crate::{
+    models::{
+        input_file::InputFile, page::Page, revision::Revision, revision_file::RevisionFile, DbConn,
+    },
+    schema::{input_files, page_tags, pages},
+};
+
+/// A tag from a page's front matter.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(InputFile))]
+#[diesel(table_name = page_tags)]
+#[diesel(primary_key(input_file_id, tag))]
+pub struct PageTag {
+    pub input_file_id: String,
+    pub tag: String,
+}
+
+type WithInputFileId<T> = diesel::dsl::Eq<page_tags::input_file_id, T>;
+type WithTag<T> = diesel::dsl::Eq<page_tags::tag, T>;
+
+#[inline]
+#[must_use]
+pub fn with_input_file_id<T>(id: T) -> WithInputFileId<T>
+where
+    T: AsExpression<Text>,
+{
+    page_tags::input_file_id.eq(id)
+}
+
+#[inline]
+#[must_use]
+pub fn with_tag<T>(tag: T) -> WithTag<T>
+where
+    T: AsExpression<Text>,
+{
+    page_tags::tag.eq(tag)
+}
+
+type All<Db> = Select<page_tags::table, AsSelect<PageTag, Db>>;
+type ByInputFileId<T, Db> = Filter<All<Db>, WithInputFileId<T>>;
+
+impl PageTag {
+    #[inline]
+    #[must_use]
+    pub fn all<Db>() -> All<Db>
+    where
+        Db: Backend,
+    {
+        page_tags::table.select(Self::as_select())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn by_input_file_id<Db>(id: &str) -> ByInputFileId<&'_ str, Db>
+    where
+        Db: Backend,
+    {
+        Self::all().filter(with_input_file_id(id))
+    }
+
+    /// All tags of pages in the revision.
+    #[inline]
+    pub fn with_revision(rev: &Revision, conn: &mut DbConn) -> QueryResult<Vec<Self>> {
+        RevisionFile::belonging_to(rev)
+            .inner_join(input_files::table.inner_join(page_tags::table))
+            .select(Self::as_select())
+            .order((page_tags::tag, page_tags::input_file_id))
+            .load(conn)
+    }
+
+    /// Pages with the tag in the revision.
+    #[inline]
+    pub fn pages(rev: &Revision, tag: &str, conn: &mut DbConn) -> QueryResult<Vec<Page>> {
+        RevisionFile::belonging_to(rev)
+            .inner_join(
+                input_files::table
+                    .inner_join(page_tags::table)
+                    .inner_join(pages::table),
+            )
+            .filter(with_tag(tag))
+            .select(Page::as_select())
+            .order(pages::input_file_id)
+            .load(conn)
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Insertable)]
+#[diesel(table_name = page_tags)]
+pub struct NewPageTag<'a> {
+    pub input_file_id: &'a str,
+    pub tag: &'a str,
+}
+
+impl<'a> NewPageTag<'a> {
+    pub fn new(input_file_id: &'a str, tag: &'a str) -> Self {
+        Self { input_file_id, tag }
+    }
+
+    pub fn create(&self, conn: &mut DbConn) -> QueryResult<usize> {
+        diesel::insert_or_ignore_into(page_tags::table)
+            .values(self)
+            .execute(conn)
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::NaiveDateTime;
+use diesel::{
+    backend::Backend,
+    expression::AsExpression,
+    helper_types::{AsSelect, Desc, Filter, Order, Select},
+    prelude::*,
+    sql_types::Text,
+};
+use serde_derive::Serialize;
+
+use crate::{
+    models::{revision::Revision, DbConn, DbId},
+    schema::publications,
+};
+
+/// A record of a revision published to a build directory.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Serialize)]
+#[diesel(belongs_to(Revision))]
+pub struct Publication {
+    pub id: DbId,
+    pub revision_id: DbId,
+    pub build_dir: String,
+    pub base_url: String,
+    pub atomic: bool,
+    /// Whether stale files were removed from the build directory.
+    pub prune: bool,
+    /// Whether draft pages were published.
+    pub include_drafts: bool,
+    /// Name of the directory in the build directory the revision was published into with `atomic`.
+    pub staging_dir: Option<String>,
+    /// The publication which was republished by a rollback.
+    pub rollback_of: Option<DbId>,
+    /// How page aliases were published.
+    pub redirects: String,
+    pub created_at: NaiveDateTime,
+}
+
+type WithBuildDir<T> = diesel::dsl::Eq<publications::build_dir, T>;
+
+#[inline]
+#[must_use]
+pub fn with_build_dir<T>(build_dir: T) -> WithBuildDir<T>
+where
+    T: AsExpression<Text>,
+{
+    publications::build_dir.eq(build_dir)
+}
+
+type All<Db> = Select<publications::table, AsSelect<Publication, Db>>;
+type OrderByCreatedAtDesc<Db> =
+    Order<All<Db>, (Desc<publications::created_at>, Desc<publications::id>)>;
+type ByBuildDir<T, Db> = Filter<OrderByCreatedAtDesc<Db>, WithBuildDir<T>>;
+
+impl Publication {
+    #[inline]
+    #[must_use]
+    pub fn all<Db>() -> All<Db>
+    where
+        Db: Backend,
+    {
+        publications::table.select(Self::as_select())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn order_by_created_at_desc<Db>() -> OrderByCreatedAtDesc<Db>
+    where
+        Db: Backend,
+    {
+        Self::all().order((publications::created_at.desc(), publications::id.desc()))
+    }
+
+    /// Publications to the build directory with the most recent first.
+    #[inline]
+    #[must_use]
+    pub fn by_build_dir<Db>(build_dir: &str) -> ByBuildDir<&'_ str, Db>
+    where
+        Db: Backend,
+    {
+        Self::order_by_created_at_desc().filter(with_build_dir(build_dir))
+    }
+
+    /// The publication whose output this publication republished.
+    #[must_use]
+    pub fn original_id(&self) -> DbId {
+        self.rollback_of.unwrap_or(self.id)
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Insertable)]
+#[diesel(table_name = publications)]
+pub struct NewPublication<'a> {
+    pub revision_id: DbId,
+    pub build_dir: &'a str,
+    pub base_url: &'a str,
+    pub atomic: bool,
+    pub prune: bool,
+    pub include_drafts: bool,
+    pub staging_dir: Option<&'a str>,
+    pub rollback_of: Option<DbId>,
+    pub redirects: &'a str,
+}
+
+impl<'a> NewPublication<'a> {
+    pub fn create(&self, conn: &mut DbConn) -> QueryResult<Publication> {
+        diesel::insert_into(publications::table)
+            .values(self)
+            .get_result(conn)
+    }
+}
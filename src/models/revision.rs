@@ -18,6 +18,8 @@ use crate::{
 pub struct Revision {
     pub id: DbId,
     pub created_at: NaiveDateTime,
+    /// Contents of the site configuration file when the revision was created.
+    pub site_config: Option<String>,
 }
 
 type WithId<T> = diesel::dsl::Eq<revisions::id, T>;
@@ -55,16 +57,17 @@ impl Revision {
 
     #[inline]
     #[must_use]
-    pub fn order_by_created_at_desc<Db>() -> Order<All<Db>, Desc<revisions::created_at>>
+    pub fn order_by_created_at_desc<Db>(
+    ) -> Order<All<Db>, (Desc<revisions::created_at>, Desc<revisions::id>)>
     where
         Db: Backend,
     {
-        Self::all().order(revisions::created_at.desc())
+        Self::all().order((revisions::created_at.desc(), revisions::id.desc()))
     }
 
-    pub fn create(conn: &mut DbConn) -> QueryResult<Self> {
+    pub fn create(site_config: Option<&str>, conn: &mut DbConn) -> QueryResult<Self> {
         diesel::insert_into(revisions::table)
-            .default_values()
+            .values(revisions::site_config.eq(site_config))
             .get_result(conn)
     }
 }
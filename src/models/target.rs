@@ -0,0 +1,91 @@
+use chrono::NaiveDateTime;
+use diesel::{
+    backend::Backend,
+    expression::AsExpression,
+    helper_types::{AsSelect, Asc, Filter, Order, Select},
+    prelude::*,
+    sql_types::Text,
+};
+use serde_derive::Serialize;
+
+use crate::{models::DbConn, schema::targets};
+
+/// A named location to publish revisions to.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Serialize)]
+#[diesel(primary_key(name))]
+pub struct Target {
+    pub name: String,
+    pub base_url: String,
+    pub build_dir: String,
+    pub atomic: bool,
+    pub prune: bool,
+    pub keep: i64,
+    pub created_at: NaiveDateTime,
+}
+
+type WithName<T> = diesel::dsl::Eq<targets::name, T>;
+
+#[inline]
+#[must_use]
+pub fn with_name<T>(name: T) -> WithName<T>
+where
+    T: AsExpression<Text>,
+{
+    targets::name.eq(name)
+}
+
+type All<Db> = Select<targets::table, AsSelect<Target, Db>>;
+type ByName<T, Db> = Filter<All<Db>, WithName<T>>;
+
+impl Target {
+    #[inline]
+    #[must_use]
+    pub fn all<Db>() -> All<Db>
+    where
+        Db: Backend,
+    {
+        targets::table.select(Self::as_select())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn order_by_name<Db>() -> Order<All<Db>, Asc<targets::name>>
+    where
+        Db: Backend,
+    {
+        Self::all().order(targets::name.asc())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn by_name<Db>(name: &str) -> ByName<&'_ str, Db>
+    where
+        Db: Backend,
+    {
+        Self::all().filter(with_name(name))
+    }
+
+    pub fn delete(name: &str, conn: &mut DbConn) -> QueryResult<usize> {
+        diesel::delete(targets::table.filter(with_name(name))).execute(conn)
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Insertable)]
+#[diesel(table_name = targets)]
+pub struct NewTarget<'a> {
+    pub name: &'a str,
+    pub base_url: &'a str,
+    pub build_dir: &'a str,
+    pub atomic: bool,
+    pub prune: bool,
+    pub keep: i64,
+}
+
+impl<'a> NewTarget<'a> {
+    pub fn create(&self, conn: &mut DbConn) -> QueryResult<Target> {
+        diesel::insert_into(targets::table)
+            .values(self)
+            .get_result(conn)
+    }
+}
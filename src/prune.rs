@@ -0,0 +1,118 @@
+//! Removes stale files from a build directory.
+//!
+//! A file is stale if it is not an output of the published revision.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ignore::WalkBuilder;
+
+/// Returns the paths under `dest` which are not in `outputs`.
+///
+/// If a directory is stale, the directory is returned but its contents are not.
+pub fn stale_paths(dest: &Path, outputs: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    if !dest.exists() {
+        return Ok(Vec::new());
+    }
+
+    let expected_files = outputs.iter().map(PathBuf::from).collect::<BTreeSet<_>>();
+    let expected_dirs = expected_files
+        .iter()
+        .flat_map(|path| path.ancestors().skip(1))
+        .map(Path::to_path_buf)
+        .collect::<BTreeSet<_>>();
+
+    let mut stale = Vec::<PathBuf>::new();
+
+    for entry in WalkBuilder::new(dest).standard_filters(false).build() {
+        let entry = entry?;
+        let disk_path = entry.path();
+        let path = disk_path.strip_prefix(dest)?;
+        if path.as_os_str().is_empty() {
+            continue;
+        }
+
+        if stale.last().is_some_and(|last| disk_path.starts_with(last)) {
+            continue;
+        }
+
+        let is_dir = entry.file_type().is_some_and(|ty| ty.is_dir());
+        let is_expected = if is_dir {
+            expected_dirs.contains(path)
+        } else {
+            expected_files.contains(path)
+        };
+
+        if !is_expected {
+            stale.push(disk_path.to_path_buf());
+        }
+    }
+
+    Ok(stale)
+}
+
+/// Removes the stale paths under `dest` and returns the removed paths.
+pub fn prune(dest: &Path, outputs: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let stale = stale_paths(dest, outputs)?;
+
+    for path in &stale {
+        tracing::trace!("Removing stale path: {}", path.display());
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(stale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_files_and_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path();
+        for path in [
+            "index.html",
+            "old.html",
+            "blog/post.html",
+            "blog/old.html",
+            "old/a.html",
+            "old/b/c.html",
+        ] {
+            let path = dest.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let outputs = ["index.html".to_string(), "blog/post.html".to_string()];
+        let mut stale = stale_paths(dest, &outputs).unwrap();
+        stale.sort();
+        assert_eq!(
+            stale,
+            vec![
+                dest.join("blog/old.html"),
+                dest.join("old"),
+                dest.join("old.html"),
+            ]
+        );
+
+        prune(dest, &outputs).unwrap();
+        assert!(stale_paths(dest, &outputs).unwrap().is_empty());
+        assert!(dest.join("blog/post.html").exists());
+    }
+
+    #[test]
+    fn missing_build_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(stale_paths(&dir.path().join("build"), &[])
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,21 +1,41 @@
 //! Publishes a build for distribution.
 
-use std::{fs, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
+use anyhow::anyhow;
 use diesel::prelude::*;
-use handlebars::Handlebars;
+use handlebars::{
+    template::{Parameter, TemplateElement},
+    Handlebars, RenderErrorReason, Template,
+};
 use itertools::Itertools;
-use lol_html::{HtmlRewriter, Settings};
-use pulldown_cmark::{html, Options, Parser};
-use serde_json::json;
+use lol_html::{html_content::Element, HtmlRewriter, Settings};
+use percent_encoding::percent_decode_str;
+use serde_json::{Map, Value};
 use url::Url;
 
-use crate::models::{
-    input_file::{InputFile, Ty},
-    page::Page,
-    revision::Revision,
-    route::Route,
-    DbConn,
+use crate::{
+    collection::{self, Collection},
+    context,
+    highlight::Highlighter,
+    manifest::Manifest,
+    markdown,
+    models::{
+        input_file::{InputFile, InputFileMeta, Ty},
+        page::Page,
+        revision::Revision,
+        route::Route,
+        DbConn,
+    },
+    redirect,
+    resolve::{self, Resolved},
+    site, taxonomy, template,
+    visibility::Unpublished,
 };
 
 fn base_relative_href(
@@ -55,26 +75,70 @@ fn route_relative_href(
     Ok(route_abs_url.make_relative(&base_url.join(path)?))
 }
 
+/// Prefix of links to a source file by its logical path such as `@/content/guide/setup.md`.
+const SOURCE_LINK_PREFIX: &str = "@/";
+
+/// Splits a link into its path and any query and fragment.
+fn split_link(href: &str) -> (&str, &str) {
+    href.split_at(href.find(['?', '#']).unwrap_or(href.len()))
+}
+
 fn rewrite_html(
     html: &[u8],
     base_url: &Url,
     route_rel_url: &str,
     rev: &Revision,
     cache_dir: &Path,
+    unpublished: &Unpublished,
     conn: &mut DbConn,
 ) -> anyhow::Result<Vec<u8>> {
     let route_abs_url = base_url.join(route_rel_url)?;
 
-    let html = rewrite_a_hrefs(html, base_url, &route_abs_url, rev, conn)?;
+    let html = rewrite_a_hrefs(html, base_url, &route_abs_url, rev, unpublished, conn)?;
     let html = rewrite_link_hrefs(&html, base_url, &route_abs_url, cache_dir, rev, conn)?;
     Ok(html)
 }
 
+/// Warns if a link points to an unpublished page.
+fn warn_unpublished(
+    rev: &Revision,
+    route_abs_url: &Url,
+    href: &str,
+    route: &Route,
+    unpublished: &Unpublished,
+) {
+    if let Some(reason) = unpublished.get(&route.input_file_id) {
+        tracing::warn!(
+            "In revision {} route: {} a href: {} points to {} page {}",
+            rev.id,
+            route_abs_url,
+            href,
+            reason,
+            route.route
+        );
+    }
+}
+
+/// Sets the element's `href` to the route followed by the link's query and fragment.
+fn set_route_href(
+    el: &mut Element<'_, '_>,
+    base_url: &Url,
+    route_abs_url: &Url,
+    route: &Route,
+    suffix: &str,
+) -> anyhow::Result<()> {
+    if let Some(href_value) = route_relative_href(base_url, route_abs_url, &route.route)? {
+        el.set_attribute("href", &format!("{href_value}{suffix}"))?;
+    }
+    Ok(())
+}
+
 fn rewrite_a_hrefs(
     html: &[u8],
     base_url: &Url,
     route_abs_url: &Url,
     rev: &Revision,
+    unpublished: &Unpublished,
     conn: &mut DbConn,
 ) -> anyhow::Result<Vec<u8>> {
     let mut output = Vec::new();
@@ -85,40 +149,47 @@ fn rewrite_a_hrefs(
                     unreachable!();
                 };
 
-                if let Ok(Some(path)) = base_relative_href(base_url, route_abs_url, &href) {
-                    if let Some(_route) = Route::by_revision_id_and_route(rev.id, &path)
-                        .first(conn)
-                        .optional()?
-                    {
-                        // Path is a valid route
+                if let Some(logical_path) = href.strip_prefix(SOURCE_LINK_PREFIX) {
+                    let (logical_path, suffix) = split_link(logical_path);
+                    let logical_path = percent_decode_str(logical_path).decode_utf8()?;
+                    if let Some(route) = resolve::source_route(rev, &logical_path, conn)? {
+                        warn_unpublished(rev, route_abs_url, &href, &route, unpublished);
+                        set_route_href(el, base_url, route_abs_url, &route, suffix)?;
                     } else {
-                        // TODO: See if an alias exists
+                        tracing::warn!(
+                            "In revision {} route: {} a href: {} points to non-existent file {}",
+                            rev.id,
+                            route_abs_url,
+                            href,
+                            logical_path
+                        );
+                    }
+                    return Ok(());
+                }
 
-                        if let Some(asset_input_file) =
-                            InputFile::asset(rev, &path, conn).optional()?
-                        {
-                            if let Some(route) = Route::by_revision_id_and_input_file_id(
-                                rev.id,
-                                &asset_input_file.id,
-                            )
-                            .first(conn)
-                            .optional()?
-                            {
-                                if let Some(href_value) =
-                                    route_relative_href(base_url, route_abs_url, &route.route)?
-                                {
-                                    el.set_attribute("href", &href_value)?;
-                                }
-                            }
-                        } else {
-                            tracing::warn!(
-                                "In revision {} route: {} a href: {} points to non-existent resource {}",
-                                rev.id,
-                                route_abs_url,
-                                href,
-                                path
-                            )
+                if let Ok(Some(path)) = base_relative_href(base_url, route_abs_url, &href) {
+                    let (path, suffix) = split_link(&path);
+                    let resolved = match percent_decode_str(path).decode_utf8() {
+                        Ok(path) => resolve::resolve(rev, &path, conn)?,
+                        Err(_) => None,
+                    };
+                    match resolved {
+                        // The path is served as is.
+                        Some(Resolved::Route(route) | Resolved::Directory(route)) => {
+                            warn_unpublished(rev, route_abs_url, &href, &route, unpublished);
+                        }
+                        // Aliases, content files and assets are published at their route.
+                        Some(Resolved::Alias(route) | Resolved::Source(route)) => {
+                            warn_unpublished(rev, route_abs_url, &href, &route, unpublished);
+                            set_route_href(el, base_url, route_abs_url, &route, suffix)?;
                         }
+                        None => tracing::warn!(
+                            "In revision {} route: {} a href: {} points to non-existent resource {}",
+                            rev.id,
+                            route_abs_url,
+                            href,
+                            path
+                        ),
                     }
                 }
 
@@ -202,11 +273,495 @@ fn rewrite_link_hrefs(
     Ok(output)
 }
 
+/// Returns true if the route's output is HTML which is rewritten or generated when published.
+pub fn is_rewritten(ty: &Ty<'_>) -> bool {
+    matches!(ty, Ty::Content(_) | Ty::Template(_)) || ty.is_html()
+}
+
+/// The templates, helpers and site configuration of a revision.
+pub struct Templates {
+    handlebars: Handlebars<'static>,
+    config: site::Config,
+    /// Section templates by the logical path of the section's `_index.md`.
+    sections: BTreeMap<String, String>,
+    /// Errors of templates which could not be parsed by name.
+    ///
+    /// Only routes which use an invalid template fail to render.
+    errors: BTreeMap<String, String>,
+    /// Pages used by the collection helpers.
+    collection: Arc<Collection>,
+}
+
+impl Templates {
+    /// Returns true if the template exists even if it is invalid.
+    pub fn has_template(&self, name: &str) -> bool {
+        self.handlebars.has_template(name) || self.errors.contains_key(name)
+    }
+
+    /// Returns the site configuration of the revision.
+    pub fn config(&self) -> &site::Config {
+        &self.config
+    }
+
+    /// Returns the template for pages in a section by the logical path of its `_index.md`.
+    pub fn section_template(&self, index: &str) -> Option<&str> {
+        self.sections.get(index).map(String::as_str)
+    }
+
+    /// Returns an invalid partial used by the template or its partials.
+    fn invalid_partial(&self, template_name: &str) -> Option<(String, &str)> {
+        let mut partials = vec![template_name.to_string()];
+        let mut seen = BTreeSet::new();
+        while let Some(name) = partials.pop() {
+            if let Some(error) = self.errors.get(&name) {
+                return Some((name, error));
+            }
+            if let Some(template) = self.handlebars.get_template(&name) {
+                if seen.insert(name) {
+                    template_partials(template, &mut partials);
+                }
+            }
+        }
+        None
+    }
+
+    /// Renders the template for a route's input file.
+    ///
+    /// Errors name the page's input file and any missing or invalid template or partial.
+    fn render(
+        &self,
+        template_name: &str,
+        data: &Map<String, Value>,
+        logical_path: &str,
+    ) -> anyhow::Result<String> {
+        if let Some(error) = self.errors.get(template_name) {
+            anyhow::bail!("template {template_name} used by {logical_path} is invalid: {error}");
+        }
+        // A missing layout falls back to the block's contents so invalid
+        // partials are found before rendering.
+        if let Some((name, error)) = self.invalid_partial(template_name) {
+            anyhow::bail!(
+                "partial {name} referenced by template {template_name} used by \
+                {logical_path} is invalid: {error}"
+            );
+        }
+
+        self.handlebars
+            .render(template_name, data)
+            .map_err(|e| match e.reason() {
+                RenderErrorReason::TemplateNotFound(name) => {
+                    anyhow!("template {name} used by {logical_path} does not exist")
+                }
+                RenderErrorReason::PartialNotFound(name) => {
+                    let template_name = e.template_name.as_deref().unwrap_or(template_name);
+                    if let Some(error) = self.errors.get(name) {
+                        anyhow!(
+                            "partial {name} referenced by template {template_name} used by \
+                            {logical_path} is invalid: {error}"
+                        )
+                    } else {
+                        anyhow!(
+                            "partial {name} referenced by template {template_name} used by \
+                            {logical_path} does not exist"
+                        )
+                    }
+                }
+                _ => anyhow::Error::new(e).context(format!(
+                    "could not render {logical_path} with template {template_name}"
+                )),
+            })
+    }
+}
+
+/// Output of a route.
+pub enum Rendered {
+    /// Contents generated or stored in the database.
+    Contents(Vec<u8>),
+    /// Path to the unmodified file in the cache directory.
+    CacheFile(PathBuf),
+}
+
+/// Renders the output for a route.
+///
+/// Returns `None` if the route does not have an output.
+///
+/// Links to `unpublished` pages produce warnings.
+pub fn render_route(
+    r: &Route,
+    rev: &Revision,
+    base_url: &Url,
+    cache_dir: &Path,
+    templates: &Templates,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> anyhow::Result<Option<Rendered>> {
+    let input_file = InputFile::by_id(&r.input_file_id).get_result(conn)?;
+
+    let ty = input_file.ty();
+    match ty {
+        Ty::Content(_) => {
+            if let Some(contents) = input_file.contents {
+                let page = Page::by_input_file_id(&input_file.id).get_result(conn)?;
+
+                let template_name = template::resolve(&input_file.logical_path, &page, templates)?;
+
+                let config = templates.config();
+                let extensions = page
+                    .front_matter
+                    .as_deref()
+                    .map(|front_matter| {
+                        markdown::Extensions::from_front_matter(
+                            front_matter,
+                            &input_file.logical_path,
+                        )
+                    })
+                    .transpose()?
+                    .unwrap_or_default()
+                    .or(config.markdown);
+                let highlighter = config
+                    .highlight
+                    .as_ref()
+                    .map(|highlight| {
+                        Highlighter::new(highlight, cache_dir, &input_file.contents_hash)
+                    })
+                    .transpose()?;
+
+                let (_, contents) = contents.split_at(usize::try_from(page.offset)?);
+                let rendered = markdown::render(
+                    core::str::from_utf8(contents)?,
+                    &extensions,
+                    highlighter.as_ref(),
+                )?;
+
+                let mut data = context::common(rev, templates.config(), base_url, &r.route)?;
+                data.insert("content".to_string(), Value::from(rendered.html));
+                data.insert("toc".to_string(), markdown::toc(&rendered.headings));
+                data.insert("page".to_string(), context::page(&page)?);
+
+                let html_output =
+                    templates.render(&template_name, &data, &input_file.logical_path)?;
+
+                let output = rewrite_html(
+                    html_output.as_bytes(),
+                    base_url,
+                    &r.route,
+                    rev,
+                    cache_dir,
+                    unpublished,
+                    conn,
+                )?;
+
+                Ok(Some(Rendered::Contents(output)))
+            } else {
+                unreachable!("content was not in database");
+            }
+        }
+        Ty::Asset(_) | Ty::Static(_) => {
+            let is_html = ty.is_html();
+            if let Some(contents) = input_file.contents {
+                if is_html {
+                    let contents = rewrite_html(
+                        &contents,
+                        base_url,
+                        &r.route,
+                        rev,
+                        cache_dir,
+                        unpublished,
+                        conn,
+                    )?;
+                    Ok(Some(Rendered::Contents(contents)))
+                } else {
+                    Ok(Some(Rendered::Contents(contents)))
+                }
+            } else {
+                let content_hash_string =
+                    format!("{:x}", input_file.contents_hash.iter().format(""));
+                let cache_path = cache_dir.join(content_hash_string);
+                assert!(cache_path.exists());
+                Ok(Some(Rendered::CacheFile(cache_path)))
+            }
+        }
+        Ty::Template(template_name) => {
+            let Some(kind) = taxonomy::kind(&r.route) else {
+                return Ok(None);
+            };
+
+            let mut data = context::common(rev, templates.config(), base_url, &r.route)?;
+            data.extend(taxonomy::context(&kind, rev, base_url, unpublished, conn)?);
+            let html_output = templates.render(template_name, &data, &input_file.logical_path)?;
+
+            let output = rewrite_html(
+                html_output.as_bytes(),
+                base_url,
+                &r.route,
+                rev,
+                cache_dir,
+                unpublished,
+                conn,
+            )?;
+
+            Ok(Some(Rendered::Contents(output)))
+        }
+        Ty::Unknown => {
+            todo!()
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn dist_route(
+    dest: &Path,
+    r: &Route,
+    rev: &Revision,
+    base_url: &Url,
+    cache_dir: &Path,
+    templates: &Templates,
+    rendered: Option<Vec<u8>>,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> anyhow::Result<()> {
+    let dest_path = dest.join(Path::new(&r.route));
+    if let Some(parent) = dest_path.parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let rendered = match rendered {
+        Some(contents) => Some(Rendered::Contents(contents)),
+        None => render_route(r, rev, base_url, cache_dir, templates, unpublished, conn)?,
+    };
+    match rendered {
+        Some(Rendered::Contents(contents)) => {
+            tracing::trace!("Writing file: {}", dest_path.display());
+            fs::write(dest_path, contents)?;
+        }
+        Some(Rendered::CacheFile(cache_path)) => {
+            tracing::trace!(
+                "Copying file {} to {}",
+                cache_path.display(),
+                dest_path.display()
+            );
+            fs::copy(cache_path, dest_path)?;
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Returns true if the parameter uses a helper or a data path segment in `names`.
+fn parameter_uses(parameter: &Parameter, names: &[&str]) -> bool {
+    match parameter {
+        Parameter::Name(name) => names.contains(&name.as_str()),
+        Parameter::Path(handlebars::Path::Relative((_, raw))) => raw
+            .split(['.', '/'])
+            .map(|segment| segment.trim_start_matches('[').trim_end_matches(']'))
+            .any(|segment| names.contains(&segment)),
+        Parameter::Path(handlebars::Path::Local(_)) | Parameter::Literal(_) => false,
+        Parameter::Subexpression(subexpression) => element_uses(&subexpression.element, names),
+    }
+}
+
+fn element_uses(element: &TemplateElement, names: &[&str]) -> bool {
+    let parameters_use =
+        |name: &Parameter, params: &[Parameter], hash: &HashMap<String, Parameter>| {
+            parameter_uses(name, names)
+                || params.iter().any(|param| parameter_uses(param, names))
+                || hash.values().any(|param| parameter_uses(param, names))
+        };
+
+    match element {
+        TemplateElement::RawString(_) | TemplateElement::Comment(_) => false,
+        TemplateElement::HtmlExpression(helper)
+        | TemplateElement::Expression(helper)
+        | TemplateElement::HelperBlock(helper) => {
+            parameters_use(&helper.name, &helper.params, &helper.hash)
+                || [&helper.template, &helper.inverse]
+                    .into_iter()
+                    .flatten()
+                    .any(|template| template_uses(template, names))
+        }
+        TemplateElement::DecoratorExpression(decorator)
+        | TemplateElement::DecoratorBlock(decorator)
+        | TemplateElement::PartialExpression(decorator)
+        | TemplateElement::PartialBlock(decorator) => {
+            parameters_use(&decorator.name, &decorator.params, &decorator.hash)
+                || decorator
+                    .template
+                    .as_ref()
+                    .is_some_and(|template| template_uses(template, names))
+        }
+    }
+}
+
+/// Returns true if the template uses a helper or data in `names`.
+///
+/// Data is matched by any segment of its path so the result may be a false
+/// positive but not a false negative.
+fn template_uses(template: &Template, names: &[&str]) -> bool {
+    template
+        .elements
+        .iter()
+        .any(|element| element_uses(element, names))
+}
+
+/// Adds the names of partials and layouts used by the template to `partials`.
+fn template_partials(template: &Template, partials: &mut Vec<String>) {
+    for element in &template.elements {
+        let (name, templates) = match element {
+            TemplateElement::PartialExpression(decorator)
+            | TemplateElement::PartialBlock(decorator) => {
+                (Some(&decorator.name), vec![&decorator.template])
+            }
+            TemplateElement::DecoratorExpression(decorator)
+            | TemplateElement::DecoratorBlock(decorator) => (None, vec![&decorator.template]),
+            TemplateElement::HelperBlock(helper) => (None, vec![&helper.template, &helper.inverse]),
+            _ => (None, Vec::new()),
+        };
+
+        match name {
+            Some(Parameter::Name(name)) => partials.push(name.clone()),
+            Some(Parameter::Path(handlebars::Path::Relative((_, raw)))) => {
+                partials.push(raw.clone());
+            }
+            _ => {}
+        }
+        for template in templates.into_iter().flatten() {
+            template_partials(template, partials);
+        }
+    }
+}
+
+/// Returns the inputs other than routes and templates which affect rewritten routes.
+fn dependencies(
+    rev: &Revision,
+    templates: &Templates,
+    conn: &mut DbConn,
+) -> anyhow::Result<Vec<String>> {
+    let mut dependencies = taxonomy::dependencies(rev, conn)?;
+    dependencies.extend(template::dependencies(rev, conn)?);
+    dependencies.extend(rev.site_config.clone());
+
+    let templates_use = |names: &[&str]| {
+        templates
+            .handlebars
+            .get_templates()
+            .values()
+            .any(|template| template_uses(template, names))
+    };
+
+    // Every page changes with each revision if a template uses the revision data.
+    if templates_use(&["revision"]) {
+        dependencies.push(format!("revision:{}", rev.id));
+    }
+
+    // Every page changes with the other pages if a template lists pages.
+    if templates_use(collection::HELPERS) {
+        dependencies.extend(templates.collection.dependencies());
+    }
+
+    Ok(dependencies)
+}
+
+/// Creates a template registry with the templates and helpers for the revision.
+///
+/// Every file under `templates/` is registered by its path relative to the
+/// directory, and also without the `.hbs` extension, so it can be used as a
+/// page template, a partial (`{{> partials/header}}`) or a layout
+/// (`{{#> base}}...{{/base}}`).
+///
+/// Invalid templates are reported when a route uses them.
+pub fn templates(
+    rev: &Revision,
+    base_url: &Url,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> anyhow::Result<Templates> {
+    let mut handlebars = Handlebars::new();
+    let mut errors = BTreeMap::new();
+    for template in InputFile::templates(rev, conn)? {
+        let Ty::Template(name) = template.ty() else {
+            continue;
+        };
+        let Some(contents) = &template.contents else {
+            anyhow::bail!("template {} is not stored", template.logical_path);
+        };
+
+        let names = [Some(name), name.strip_suffix(".hbs")];
+        let result = core::str::from_utf8(contents)
+            .map_err(|_| "not UTF-8".to_string())
+            .and_then(|contents| {
+                handlebars
+                    .register_template_string(name, contents)
+                    .map_err(|e| e.to_string())?;
+                if let Some(stem) = name.strip_suffix(".hbs") {
+                    handlebars
+                        .register_template_string(stem, contents)
+                        .map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            });
+        if let Err(error) = result {
+            tracing::warn!(
+                "In revision {} template {} is invalid: {error}",
+                rev.id,
+                template.logical_path
+            );
+            for name in names.into_iter().flatten() {
+                errors.insert(name.to_string(), error.clone());
+            }
+        }
+    }
+
+    let collection = Arc::new(Collection::load(rev, base_url, unpublished, conn)?);
+    collection::register(&mut handlebars, &collection);
+    Ok(Templates {
+        handlebars,
+        config: site::Config::load(rev)?,
+        sections: template::section_templates(rev, conn)?,
+        errors,
+        collection,
+    })
+}
+
+/// Returns the paths of every output of the revision relative to the build directory.
+pub fn output_paths(
+    rev: &Revision,
+    base_url: &Url,
+    redirects: redirect::Style,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> anyhow::Result<Vec<String>> {
+    let unpublished_terms = taxonomy::unpublished_routes(rev, unpublished, conn)?;
+    let mut paths = Route::with_revision(rev, conn)?
+        .into_iter()
+        .filter(|r| {
+            !unpublished.contains_key(&r.input_file_id) && !unpublished_terms.contains(&r.route)
+        })
+        .map(|r| r.route)
+        .collect::<Vec<_>>();
+    paths.extend(redirect::outputs(rev, base_url, redirects, unpublished, conn)?.into_keys());
+    Ok(paths)
+}
+
+/// Contents of routes which were already rendered by route.
+pub type RenderedRoutes = BTreeMap<String, Vec<u8>>;
+
+/// Publishes the revision into the build directory.
+///
+/// Routes in `rendered` are written without rendering them again. Routes of
+/// `unpublished` pages are not written.
+#[allow(clippy::too_many_arguments)]
 pub fn dist_revision(
     dest: &Path,
     rev: &Revision,
     base_url: &Url,
     cache_dir: &Path,
+    full: bool,
+    redirects: redirect::Style,
+    mut rendered: RenderedRoutes,
+    unpublished: &Unpublished,
     conn: &mut DbConn,
 ) -> anyhow::Result<()> {
     if dest.exists() {
@@ -215,97 +770,320 @@ pub fn dist_revision(
         fs::create_dir_all(dest)?;
     }
 
-    let routes = Route::with_revision(rev, conn)?;
+    let unpublished_terms = taxonomy::unpublished_routes(rev, unpublished, conn)?;
+    let (routes, unpublished_routes) = Route::with_revision(rev, conn)?
+        .into_iter()
+        .partition::<Vec<_>, _>(|r| {
+            !unpublished.contains_key(&r.input_file_id) && !unpublished_terms.contains(&r.route)
+        });
+    for r in &unpublished_routes {
+        if let Some(reason) = unpublished.get(&r.input_file_id) {
+            tracing::info!("Skipped {reason} page {}", r.route);
+        } else {
+            tracing::info!("Skipped tag page {} without published pages", r.route);
+        }
+    }
+
+    let files = InputFileMeta::with_revision(rev, conn)?;
+    let files = files
+        .iter()
+        .map(|f| (f.id.as_str(), f.ty()))
+        .collect::<BTreeMap<_, _>>();
+    let redirect_outputs = redirect::outputs(rev, base_url, redirects, unpublished, conn)?;
+    let templates = templates(rev, base_url, unpublished, conn)?;
+
+    let manifest = Manifest::new(
+        rev.id,
+        base_url,
+        routes
+            .iter()
+            .map(|r| (r.route.clone(), r.input_file_id.clone()))
+            .chain(redirect_outputs.iter().map(|(path, contents)| {
+                let hash = blake3::hash(contents.as_bytes());
+                (
+                    path.clone(),
+                    format!("redirect:{:x}", hash.as_bytes().iter().format("")),
+                )
+            }))
+            .collect(),
+        &files,
+        &dependencies(rev, &templates, conn)?,
+    );
+    let previous = if full {
+        None
+    } else {
+        Manifest::read(cache_dir, dest)?
+    };
+    Manifest::remove(cache_dir, dest)?;
 
-    let mut templates = Handlebars::new();
+    if let Some(previous) = &previous {
+        for route in previous
+            .routes
+            .keys()
+            .filter(|route| !manifest.routes.contains_key(*route))
+        {
+            let dest_path = dest.join(Path::new(route));
+            if dest_path.exists() {
+                tracing::trace!("Removing file: {}", dest_path.display());
+                fs::remove_file(dest_path)?;
+            }
+        }
+    }
+
+    let mut skipped = 0;
 
     for r in routes {
-        let dest_path = dest.join(Path::new(&r.route));
-        if let Some(parent) = dest_path.parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent)?;
+        if let Some(previous) = &previous {
+            let is_rewritten = files
+                .get(r.input_file_id.as_str())
+                .is_some_and(is_rewritten);
+            if previous.is_unchanged(&manifest, &r.route, is_rewritten)
+                && dest.join(Path::new(&r.route)).exists()
+            {
+                skipped += 1;
+                continue;
             }
         }
-        let input_file = InputFile::by_id(&r.input_file_id).get_result(conn)?;
-
-        let ty = input_file.ty();
-        match ty {
-            Ty::Content(_) => {
-                if let Some(contents) = input_file.contents {
-                    let page = Page::by_input_file_id(&input_file.id).get_result(conn)?;
-
-                    if let Some(template_name) = page.template {
-                        if !templates.has_template(&template_name) {
-                            let template = InputFile::template(rev, &template_name, conn)?;
-                            let template_contents = template.contents.unwrap();
-                            let template_string = core::str::from_utf8(&template_contents)?;
-                            templates.register_template_string(&template_name, template_string)?;
-                        }
 
-                        let (_, contents) = contents.split_at(usize::try_from(page.offset)?);
-                        let contents = core::str::from_utf8(contents)?;
+        dist_route(
+            dest,
+            &r,
+            rev,
+            base_url,
+            cache_dir,
+            &templates,
+            rendered.remove(&r.route),
+            unpublished,
+            conn,
+        )?;
+    }
 
-                        let options = Options::empty();
-                        let parser = Parser::new_ext(contents, options);
+    for (path, contents) in redirect_outputs {
+        let dest_path = dest.join(Path::new(&path));
+        if let Some(previous) = &previous {
+            if previous.is_unchanged(&manifest, &path, false) && dest_path.exists() {
+                skipped += 1;
+                continue;
+            }
+        }
 
-                        let mut contents = String::new();
-                        html::push_html(&mut contents, parser);
+        if let Some(parent) = dest_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        tracing::trace!("Writing redirect: {}", dest_path.display());
+        fs::write(dest_path, contents)?;
+    }
 
-                        let html_output =
-                            templates.render(&template_name, &json!({ "content": contents }))?;
+    if skipped > 0 {
+        tracing::info!("Skipped {skipped} unchanged routes");
+    }
 
-                        let output = rewrite_html(
-                            html_output.as_bytes(),
-                            base_url,
-                            &r.route,
-                            rev,
-                            cache_dir,
-                            conn,
-                        )?;
+    manifest.write(cache_dir, dest)?;
 
-                        tracing::trace!("Writing content to file: {}", dest_path.display());
-                        fs::write(dest_path, output)?;
-                    } else {
-                        todo!();
-                    }
-                } else {
-                    unreachable!("content was not in database");
-                }
-            }
-            Ty::Asset(_) | Ty::Static(_) => {
-                if let Some(contents) = &input_file.contents {
-                    if ty.is_html() {
-                        tracing::trace!("Writing file: {}", dest_path.display());
-                        let contents =
-                            rewrite_html(contents, base_url, &r.route, rev, cache_dir, conn)?;
-
-                        fs::write(dest_path, contents)?;
-                    } else {
-                        tracing::trace!(
-                            "Writing file from database contents: {}",
-                            dest_path.display()
-                        );
-                        fs::write(dest_path, contents)?;
-                    }
-                } else {
-                    let content_hash_string =
-                        format!("{:x}", input_file.contents_hash.iter().format(""));
-                    let cache_path = cache_dir.join(content_hash_string);
-                    assert!(cache_path.exists());
-                    tracing::trace!(
-                        "Copying file {} to {}",
-                        cache_path.display(),
-                        dest_path.display()
-                    );
-                    fs::copy(cache_path, dest_path)?;
-                }
-            }
-            Ty::Template(_) => {}
-            Ty::Unknown => {
-                todo!()
-            }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    use crate::{
+        testing::{warnings, Site},
+        visibility::Visibility,
+    };
+
+    fn uses(template: &str, names: &[&str]) -> bool {
+        template_uses(&Template::compile(template).unwrap(), names)
+    }
+
+    #[test]
+    fn templates_use_names() {
+        assert!(uses("{{revision.id}}", &["revision"]));
+        assert!(uses("{{#with revision}}{{id}}{{/with}}", &["revision"]));
+        assert!(uses("{{> footer id=revision.id}}", &["revision"]));
+        assert!(!uses("{{title}}", &["revision"]));
+        // Text which is not an expression is ignored.
+        assert!(!uses(
+            "<!-- revision -->{{! pages }}",
+            &["revision", "pages"]
+        ));
+
+        assert!(uses(
+            "{{#each (pages \"blog\")}}{{title}}{{/each}}",
+            collection::HELPERS
+        ));
+        assert!(uses(
+            "{{#if page}}{{else}}{{#with (prev_page)}}{{title}}{{/with}}{{/if}}",
+            collection::HELPERS
+        ));
+        assert!(!uses("{{#each pages_list}}{{/each}}", collection::HELPERS));
+    }
+
+    fn render(site: &Site, rev: &Revision, route: &str) -> anyhow::Result<String> {
+        let mut conn = site.conn();
+        let base_url = Url::parse("https://example.com/").unwrap();
+        let unpublished = Unpublished::new();
+        let templates = templates(rev, &base_url, &unpublished, &mut conn)?;
+        let r = Route::by_revision_id_and_route(rev.id, route).get_result(&mut conn)?;
+        match render_route(
+            &r,
+            rev,
+            &base_url,
+            &site.cache_dir(),
+            &templates,
+            &unpublished,
+            &mut conn,
+        )? {
+            Some(Rendered::Contents(contents)) => Ok(String::from_utf8(contents)?),
+            _ => anyhow::bail!("{route} has no contents"),
         }
     }
 
-    Ok(())
+    #[test]
+    fn split_links() {
+        assert_eq!(split_link("a.md"), ("a.md", ""));
+        assert_eq!(split_link("a.md#x"), ("a.md", "#x"));
+        assert_eq!(split_link("a.md?v=1#x"), ("a.md", "?v=1#x"));
+        assert_eq!(split_link("a.md#x?y"), ("a.md", "#x?y"));
+        assert_eq!(split_link("#x"), ("", "#x"));
+    }
+
+    #[test]
+    fn content_links() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write("content/about.md", "About\n");
+        site.write("content/guide/setup.md", "Setup\n");
+        site.write(
+            "content/guide/intro.md",
+            "[a](../about.md) \
+            [b](setup.md?v=1#install) \
+            [c](@/content/about.md#top) \
+            [d](setup.html#install) \
+            [e](missing.md#x) \
+            [f](@/content/missing.md)\n",
+        );
+        let rev = site.create();
+
+        let (html, warnings) = warnings(|| render(&site, &rev, "guide/intro.html").unwrap());
+        assert_eq!(
+            html,
+            "<p><a href=\"../about.html\">a</a> \
+            <a href=\"setup.html?v=1#install\">b</a> \
+            <a href=\"../about.html#top\">c</a> \
+            <a href=\"setup.html#install\">d</a> \
+            <a href=\"missing.md#x\">e</a> \
+            <a href=\"@/content/missing.md\">f</a></p>\n"
+        );
+
+        // A route with a fragment is found so only the missing targets are reported.
+        let warnings = warnings.lines().collect::<Vec<_>>();
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        assert!(warnings[0]
+            .ends_with("a href: missing.md#x points to non-existent resource guide/missing.md"));
+        assert!(warnings[1].ends_with(
+            "a href: @/content/missing.md points to non-existent file content/missing.md"
+        ));
+    }
+
+    #[test]
+    fn invalid_templates() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "<p>{{{content}}}</p>");
+        site.write("templates/broken.hbs", "{{#if page}}");
+        site.write("templates/base.hbs", "{{#if}}");
+        site.write("templates/layout.hbs", "{{#> base}}{{{content}}}{{/base}}");
+        site.write(
+            "templates/partial.hbs",
+            "{{> partials/header}}{{{content}}}",
+        );
+        site.write("content/index.md", "Home\n");
+        for template in ["broken", "layout", "partial"] {
+            site.write(
+                &format!("content/{template}.md"),
+                &format!("+++\ntemplate = \"{template}.hbs\"\n+++\nPage\n"),
+            );
+        }
+        let rev = site.create();
+
+        // Pages with valid templates are rendered.
+        assert_eq!(
+            render(&site, &rev, "index.html").unwrap(),
+            "<p><p>Home</p>\n</p>"
+        );
+
+        let error = render(&site, &rev, "broken.html").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("template broken.hbs used by content/broken.md is invalid"),
+            "{error}"
+        );
+        let error = render(&site, &rev, "layout.html").unwrap_err();
+        assert!(
+            error.to_string().starts_with(
+                "partial base referenced by template layout.hbs used by content/layout.md is invalid"
+            ),
+            "{error}"
+        );
+        let error = render(&site, &rev, "partial.html").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "partial partials/header referenced by template partial.hbs used by \
+            content/partial.md does not exist"
+        );
+    }
+
+    #[test]
+    fn draft_only_tags() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write("templates/tag.hbs", "{{tag.name}}");
+        site.write(
+            "content/post.md",
+            "+++\ntitle = \"Post\"\ntags = [\"Rust\"]\n+++\nPost\n",
+        );
+        site.write(
+            "content/draft.md",
+            "+++\ntitle = \"Draft\"\ndraft = true\ntags = [\"Rust\", \"Secret Launch\"]\n+++\nDraft\n",
+        );
+        let rev = site.create();
+        let mut conn = site.conn();
+        let base_url = Url::parse("https://example.com/").unwrap();
+        let unpublished = Visibility {
+            include_drafts: false,
+            now: Utc::now().naive_utc(),
+        }
+        .unpublished(&rev, &mut conn)
+        .unwrap();
+
+        let paths = output_paths(
+            &rev,
+            &base_url,
+            redirect::Style::Html,
+            &unpublished,
+            &mut conn,
+        )
+        .unwrap();
+        assert!(paths.contains(&"tags/rust/index.html".to_string()));
+        assert!(!paths.contains(&"tags/secret-launch/index.html".to_string()));
+
+        let dest = site.path().join("out");
+        dist_revision(
+            &dest,
+            &rev,
+            &base_url,
+            &site.cache_dir(),
+            true,
+            redirect::Style::Html,
+            RenderedRoutes::new(),
+            &unpublished,
+            &mut conn,
+        )
+        .unwrap();
+        assert!(dest.join("tags/rust/index.html").exists());
+        assert!(!dest.join("tags/secret-launch/index.html").exists());
+        assert!(!dest.join("draft.html").exists());
+    }
 }
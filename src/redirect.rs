@@ -0,0 +1,281 @@
+//! Redirects from page aliases to the canonical routes.
+//!
+//! Aliases are published either as HTML stubs at each alias path or as a
+//! single `_redirects` file understood by hosts such as Netlify and Cloudflare.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use clap::ValueEnum;
+use diesel::prelude::*;
+use serde_derive::Deserialize;
+use url::Url;
+
+use crate::{
+    models::{page_alias::PageAlias, revision::Revision, route::Route, DbConn},
+    visibility::Unpublished,
+};
+
+/// Name of the consolidated redirects file.
+pub const FILE_NAME: &str = "_redirects";
+
+/// How page aliases are published.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    /// An HTML page with a meta refresh at each alias path.
+    #[default]
+    Html,
+    /// A single `_redirects` file.
+    File,
+}
+
+impl Style {
+    /// Name of the style as used on the command line and in the site configuration.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Style::Html => "html",
+            Style::File => "file",
+        }
+    }
+}
+
+/// A redirect from an alias to a route.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Redirect {
+    /// Alias relative to the base URL.
+    pub alias: String,
+    pub route: String,
+}
+
+impl Redirect {
+    /// Path of the HTML stub for the alias.
+    ///
+    /// Aliases without an extension are treated as directories.
+    fn stub_path(&self) -> String {
+        if self.alias.is_empty() || self.alias.ends_with('/') {
+            format!("{}index.html", self.alias)
+        } else if self
+            .alias
+            .rsplit('/')
+            .next()
+            .is_some_and(|name| name.contains('.'))
+        {
+            self.alias.clone()
+        } else {
+            format!("{}/index.html", self.alias)
+        }
+    }
+
+    /// Path which identifies the redirect in the style.
+    ///
+    /// Aliases which differ only by a trailing slash share an HTML stub but
+    /// are separate `_redirects` entries.
+    fn path(&self, style: Style) -> String {
+        match style {
+            Style::Html => self.stub_path(),
+            Style::File => self.alias.clone(),
+        }
+    }
+
+    fn stub(&self, base_url: &Url) -> anyhow::Result<String> {
+        let url = handlebars::html_escape(base_url.join(&self.route)?.as_str());
+        Ok(format!(
+            "<!DOCTYPE html>\n\
+            <html>\n\
+            <head>\n\
+            <meta charset=\"utf-8\">\n\
+            <title>Redirecting to {url}</title>\n\
+            <link rel=\"canonical\" href=\"{url}\">\n\
+            <meta name=\"robots\" content=\"noindex\">\n\
+            <meta http-equiv=\"refresh\" content=\"0; url={url}\">\n\
+            </head>\n\
+            <body><a href=\"{url}\">Redirecting to {url}</a></body>\n\
+            </html>\n"
+        ))
+    }
+}
+
+/// Returns the redirect for each page alias in the revision.
+///
+/// Aliases which are the same as an existing route or belong to an
+/// unpublished page are ignored. If pages of different routes have the same
+/// alias in the style, only the first is kept.
+pub fn redirects(
+    rev: &Revision,
+    style: Style,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> anyhow::Result<Vec<Redirect>> {
+    let routes = Route::with_revision(rev, conn)?;
+
+    let mut redirects = Vec::new();
+    for alias in PageAlias::with_revision(rev, conn)? {
+        if unpublished.contains_key(&alias.input_file_id) {
+            continue;
+        }
+
+        let Some(route) = routes
+            .iter()
+            .find(|r| r.input_file_id == alias.input_file_id)
+        else {
+            continue;
+        };
+
+        let redirect = Redirect {
+            alias: alias.alias,
+            route: route.route.clone(),
+        };
+
+        if routes.iter().any(|r| r.route == redirect.stub_path()) {
+            tracing::warn!(
+                "In revision {} alias {} of {} is the same as an existing route",
+                rev.id,
+                redirect.alias,
+                redirect.route
+            );
+            continue;
+        }
+
+        if let Some(existing) = redirects
+            .iter()
+            .find(|r: &&Redirect| r.path(style) == redirect.path(style))
+        {
+            if existing.route != redirect.route {
+                tracing::warn!(
+                    "In revision {} alias {} of {} is also an alias of {}",
+                    rev.id,
+                    redirect.alias,
+                    redirect.route,
+                    existing.route
+                );
+            }
+            continue;
+        }
+
+        redirects.push(redirect);
+    }
+
+    Ok(redirects)
+}
+
+/// Returns the contents of the published redirect files by path.
+pub fn outputs(
+    rev: &Revision,
+    base_url: &Url,
+    style: Style,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let redirects = redirects(rev, style, unpublished, conn)?;
+
+    let mut outputs = BTreeMap::new();
+    match style {
+        Style::Html => {
+            for redirect in &redirects {
+                outputs.insert(redirect.stub_path(), redirect.stub(base_url)?);
+            }
+        }
+        Style::File => {
+            if !redirects.is_empty() {
+                let mut contents = String::new();
+                for redirect in &redirects {
+                    writeln!(
+                        contents,
+                        "{} {} 301",
+                        base_url.join(&redirect.alias)?.path(),
+                        base_url.join(&redirect.route)?.path()
+                    )?;
+                }
+                outputs.insert(FILE_NAME.to_string(), contents);
+            }
+        }
+    }
+
+    Ok(outputs)
+}
+
+/// Returns the route for an alias in the revision.
+pub fn find_route(rev: &Revision, alias: &str, conn: &mut DbConn) -> QueryResult<Option<Route>> {
+    let Some(alias) = PageAlias::find(rev, alias, conn)? else {
+        return Ok(None);
+    };
+
+    Route::by_revision_id_and_input_file_id(rev.id, &alias.input_file_id)
+        .first(conn)
+        .optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Site;
+
+    fn site() -> Site {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write(
+            "content/about.md",
+            "+++\ntitle = \"About\"\naliases = [\"/old-about\", \"/me.html\", \"/index.html\"]\n+++\nAbout\n",
+        );
+        site.write(
+            "content/contact.md",
+            "+++\ntitle = \"Contact\"\naliases = [\"old-about/\"]\n+++\nContact\n",
+        );
+        site.write("content/index.md", "+++\ntitle = \"Home\"\n+++\nHome\n");
+        site
+    }
+
+    #[test]
+    fn html_stubs() {
+        let site = site();
+        let rev = site.create();
+        let base_url = Url::parse("https://example.com/blog/").unwrap();
+
+        let outputs = outputs(
+            &rev,
+            &base_url,
+            Style::Html,
+            &Unpublished::new(),
+            &mut site.conn(),
+        )
+        .unwrap();
+        // The alias of an existing route is ignored and only one page gets a
+        // conflicting alias.
+        assert_eq!(
+            outputs.keys().collect::<Vec<_>>(),
+            vec!["me.html", "old-about/index.html"]
+        );
+        assert!(outputs["me.html"].contains(
+            "<meta http-equiv=\"refresh\" content=\"0; url=https://example.com/blog/about.html\">"
+        ));
+    }
+
+    #[test]
+    fn redirects_file() {
+        let site = site();
+        let rev = site.create();
+        let base_url = Url::parse("https://example.com/blog/").unwrap();
+
+        let outputs = outputs(
+            &rev,
+            &base_url,
+            Style::File,
+            &Unpublished::new(),
+            &mut site.conn(),
+        )
+        .unwrap();
+        assert_eq!(outputs.keys().collect::<Vec<_>>(), vec![FILE_NAME]);
+        // Aliases with and without a trailing slash are different redirects.
+        let mut lines = outputs[FILE_NAME].lines().collect::<Vec<_>>();
+        lines.sort_unstable();
+        assert_eq!(
+            lines,
+            vec![
+                "/blog/me.html /blog/about.html 301",
+                "/blog/old-about /blog/about.html 301",
+                "/blog/old-about/ /blog/contact.html 301",
+            ]
+        );
+    }
+}
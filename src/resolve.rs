@@ -0,0 +1,136 @@
+//! Resolves paths relative to the base URL to the routes of a revision.
+//!
+//! The server, link rewriting and link checking resolve paths the same way so
+//! a link which works when served also works when published.
+
+use diesel::prelude::*;
+
+use crate::{
+    models::{input_file::InputFile, revision::Revision, route::Route, DbConn},
+    redirect,
+};
+
+/// How a path resolves to a route.
+#[derive(Debug, PartialEq)]
+pub enum Resolved {
+    /// The route's path, a directory path with a trailing slash for its
+    /// `index.html` route, or a path without the `.html` extension.
+    Route(Route),
+    /// A directory path without a trailing slash for its `index.html` route.
+    Directory(Route),
+    /// An alias of the route's page.
+    Alias(Route),
+    /// The logical path of a content file under `content/` or of an asset
+    /// under `assets/`, which are published at another path.
+    Source(Route),
+}
+
+impl Resolved {
+    #[must_use]
+    pub fn route(&self) -> &Route {
+        match self {
+            Resolved::Route(route)
+            | Resolved::Directory(route)
+            | Resolved::Alias(route)
+            | Resolved::Source(route) => route,
+        }
+    }
+}
+
+/// Returns the route of the source file with the logical path.
+pub fn source_route(
+    rev: &Revision,
+    logical_path: &str,
+    conn: &mut DbConn,
+) -> QueryResult<Option<Route>> {
+    let Some(input_file) = InputFile::find(rev, logical_path, conn)? else {
+        return Ok(None);
+    };
+    Route::by_revision_id_and_input_file_id(rev.id, &input_file.id)
+        .first(conn)
+        .optional()
+}
+
+/// Resolves a percent-decoded path relative to the base URL without a query or fragment.
+pub fn resolve(rev: &Revision, path: &str, conn: &mut DbConn) -> QueryResult<Option<Resolved>> {
+    let find = |route: &str, conn: &mut DbConn| {
+        Route::by_revision_id_and_route(rev.id, route)
+            .first(conn)
+            .optional()
+    };
+
+    if path.is_empty() || path.ends_with('/') {
+        if let Some(route) = find(&format!("{path}index.html"), conn)? {
+            return Ok(Some(Resolved::Route(route)));
+        }
+    } else {
+        for route in [path.to_string(), format!("{path}.html")] {
+            if let Some(route) = find(&route, conn)? {
+                return Ok(Some(Resolved::Route(route)));
+            }
+        }
+        if let Some(route) = find(&format!("{path}/index.html"), conn)? {
+            return Ok(Some(Resolved::Directory(route)));
+        }
+    }
+
+    if let Some(route) = redirect::find_route(rev, path, conn)? {
+        return Ok(Some(Resolved::Alias(route)));
+    }
+
+    let logical_path = if path.ends_with(".md") {
+        format!("content/{path}")
+    } else {
+        format!("assets/{path}")
+    };
+    Ok(source_route(rev, &logical_path, conn)?.map(Resolved::Source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Site;
+
+    #[test]
+    fn resolve_paths() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write("content/index.md", "Home\n");
+        site.write(
+            "content/about.md",
+            "+++\naliases = [\"me/\", \"old.php\"]\n+++\nAbout\n",
+        );
+        site.write("content/blog/index.md", "Blog\n");
+        site.write("assets/main.css", "body{color:red}");
+        let rev = site.create();
+        let resolve = |path: &str| {
+            resolve(&rev, path, &mut site.conn())
+                .unwrap()
+                .map(|resolved| {
+                    let kind = match &resolved {
+                        Resolved::Route(_) => "route",
+                        Resolved::Directory(_) => "directory",
+                        Resolved::Alias(_) => "alias",
+                        Resolved::Source(_) => "source",
+                    };
+                    (kind, resolved.route().route.clone())
+                })
+        };
+        let resolved = |kind, route: &str| Some((kind, route.to_string()));
+
+        assert_eq!(resolve(""), resolved("route", "index.html"));
+        assert_eq!(resolve("about.html"), resolved("route", "about.html"));
+        assert_eq!(resolve("about"), resolved("route", "about.html"));
+        assert_eq!(resolve("blog/"), resolved("route", "blog/index.html"));
+        assert_eq!(resolve("blog"), resolved("directory", "blog/index.html"));
+        assert_eq!(resolve("me/"), resolved("alias", "about.html"));
+        assert_eq!(resolve("old.php"), resolved("alias", "about.html"));
+        assert_eq!(resolve("about.md"), resolved("source", "about.html"));
+        let (kind, route) = resolve("main.css").unwrap();
+        assert_eq!(kind, "source");
+        assert!(route.starts_with("main.") && route != "main.css");
+        assert_eq!(resolve("about/"), None);
+        assert_eq!(resolve("missing"), None);
+        assert_eq!(resolve("missing.md"), None);
+    }
+}
@@ -1,5 +1,18 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    use crate::sqlite_mapping::*;
+
+    file_stats (disk_path) {
+        disk_path -> Text,
+        size -> Integer,
+        modified -> Integer,
+        inode -> Nullable<Integer>,
+        input_file_id -> Text,
+        recorded_at -> Integer,
+    }
+}
+
 diesel::table! {
     use crate::sqlite_mapping::*;
 
@@ -21,6 +34,19 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use crate::sqlite_mapping::*;
+
+    page_headings (revision_id, input_file_id, position) {
+        revision_id -> Integer,
+        input_file_id -> Text,
+        position -> Integer,
+        level -> Integer,
+        anchor -> Text,
+        title -> Text,
+    }
+}
+
 diesel::table! {
     use crate::sqlite_mapping::*;
 
@@ -50,6 +76,24 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use crate::sqlite_mapping::*;
+
+    publications (id) {
+        id -> Integer,
+        revision_id -> Integer,
+        build_dir -> Text,
+        base_url -> Text,
+        atomic -> Bool,
+        prune -> Bool,
+        include_drafts -> Bool,
+        staging_dir -> Nullable<Text>,
+        rollback_of -> Nullable<Integer>,
+        redirects -> Text,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     use crate::sqlite_mapping::*;
 
@@ -65,6 +109,7 @@ diesel::table! {
     revisions (id) {
         id -> Integer,
         created_at -> Timestamp,
+        site_config -> Nullable<Text>,
     }
 }
 
@@ -78,20 +123,42 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use crate::sqlite_mapping::*;
+
+    targets (name) {
+        name -> Text,
+        base_url -> Text,
+        build_dir -> Text,
+        atomic -> Bool,
+        prune -> Bool,
+        keep -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(file_stats -> input_files (input_file_id));
 diesel::joinable!(page_aliases -> input_files (input_file_id));
+diesel::joinable!(page_headings -> input_files (input_file_id));
+diesel::joinable!(page_headings -> revisions (revision_id));
 diesel::joinable!(page_tags -> input_files (input_file_id));
 diesel::joinable!(pages -> input_files (input_file_id));
+diesel::joinable!(publications -> revisions (revision_id));
 diesel::joinable!(revision_files -> input_files (input_file_id));
 diesel::joinable!(revision_files -> revisions (revision_id));
 diesel::joinable!(routes -> input_files (input_file_id));
 diesel::joinable!(routes -> revisions (revision_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    file_stats,
     input_files,
     page_aliases,
+    page_headings,
     page_tags,
     pages,
+    publications,
     revision_files,
     revisions,
     routes,
+    targets,
 );
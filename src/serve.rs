@@ -0,0 +1,344 @@
+//! Serves a revision directly from the database.
+//!
+//! Routes are rendered on each request in the same way they are published so
+//! a revision can be previewed without writing a build directory.
+//!
+//! Drafts and pages outside of their publish and expiry dates are served too
+//! unless unpublished pages are hidden.
+
+use std::{fs::File, net::SocketAddr, path::Path};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use lol_html::{html_content::ContentType, HtmlRewriter, Settings};
+use percent_encoding::percent_decode_str;
+use tiny_http::{Header, Method, Request, Response, ResponseBox, Server};
+use url::Url;
+
+use crate::{
+    models::{revision::Revision, DbConn},
+    publish::{self, Rendered, Templates},
+    resolve::{self, Resolved},
+    taxonomy,
+    visibility::{Unpublished, Visibility},
+};
+
+fn content_type(route: &str) -> &'static str {
+    let extension = Path::new(route)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+fn header(name: &str, value: &str) -> anyhow::Result<Header> {
+    Header::from_bytes(name.as_bytes(), value.as_bytes())
+        .map_err(|()| anyhow::anyhow!("invalid {name} header: {value}"))
+}
+
+fn not_found() -> ResponseBox {
+    Response::from_string("Not Found")
+        .with_status_code(404)
+        .boxed()
+}
+
+pub fn internal_server_error() -> ResponseBox {
+    Response::from_string("Internal Server Error")
+        .with_status_code(500)
+        .boxed()
+}
+
+fn bad_request() -> ResponseBox {
+    Response::from_string("Bad Request")
+        .with_status_code(400)
+        .boxed()
+}
+
+/// Redirects to the percent-encoded path.
+fn redirect(path: &str) -> anyhow::Result<ResponseBox> {
+    let mut location = Url::parse("http://localhost/").expect("URL should be valid");
+    location.set_path(path);
+    Ok(Response::empty(301)
+        .with_header(header("Location", location.path())?)
+        .boxed())
+}
+
+/// Options for responding to requests.
+pub struct Context<'a> {
+    pub base_url: Url,
+    pub cache_dir: &'a Path,
+    /// HTML inserted at the end of the body of HTML responses.
+    pub body_html: Option<&'a str>,
+    /// Whether pages which would not be published now are hidden.
+    pub hide_unpublished: bool,
+}
+
+impl Context<'_> {
+    /// Returns the pages in the revision which are not served.
+    pub fn unpublished(&self, rev: &Revision, conn: &mut DbConn) -> QueryResult<Unpublished> {
+        if !self.hide_unpublished {
+            return Ok(Unpublished::new());
+        }
+
+        Visibility {
+            include_drafts: false,
+            now: Utc::now().naive_utc(),
+        }
+        .unpublished(rev, conn)
+    }
+}
+
+/// Inserts HTML at the end of the `body` element.
+///
+/// The HTML is appended to the document if it has no `body` element.
+fn append_to_body(contents: &[u8], html: &str) -> anyhow::Result<Vec<u8>> {
+    let mut has_body = false;
+    let mut output = Vec::new();
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![lol_html::element!("body", |el| {
+                has_body = true;
+                el.append(html, ContentType::Html);
+                Ok(())
+            })],
+            ..Settings::default()
+        },
+        |c: &[u8]| output.extend_from_slice(c),
+    );
+    rewriter.write(contents)?;
+    rewriter.end()?;
+
+    if !has_body {
+        output.extend_from_slice(html.as_bytes());
+    }
+    Ok(output)
+}
+
+fn respond(
+    url: &str,
+    rev: &Revision,
+    ctx: &Context<'_>,
+    templates: &Templates,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> anyhow::Result<ResponseBox> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let Ok(path) = percent_decode_str(path.trim_start_matches('/')).decode_utf8() else {
+        return Ok(bad_request());
+    };
+
+    // Source files are only served at their routes.
+    let route = match resolve::resolve(rev, &path, conn)? {
+        Some(Resolved::Route(route)) => route,
+        Some(Resolved::Directory(_)) => return redirect(&format!("/{path}/")),
+        Some(Resolved::Alias(route)) => return redirect(&format!("/{}", route.route)),
+        Some(Resolved::Source(_)) | None => return Ok(not_found()),
+    };
+    if unpublished.contains_key(&route.input_file_id)
+        || taxonomy::unpublished_routes(rev, unpublished, conn)?.contains(&route.route)
+    {
+        return Ok(not_found());
+    }
+
+    let content_type = content_type(&route.route);
+
+    let response = match publish::render_route(
+        &route,
+        rev,
+        &ctx.base_url,
+        ctx.cache_dir,
+        templates,
+        unpublished,
+        conn,
+    )? {
+        Some(Rendered::Contents(mut contents)) => {
+            if let Some(body_html) = ctx.body_html {
+                if content_type.starts_with("text/html") {
+                    contents = append_to_body(&contents, body_html)?;
+                }
+            }
+            Response::from_data(contents)
+                .with_header(header("Content-Type", content_type)?)
+                .boxed()
+        }
+        Some(Rendered::CacheFile(cache_path)) => Response::from_file(File::open(cache_path)?)
+            .with_header(header("Content-Type", content_type)?)
+            .boxed(),
+        None => not_found(),
+    };
+
+    Ok(response)
+}
+
+pub fn handle(
+    request: Request,
+    rev: &Revision,
+    ctx: &Context<'_>,
+    templates: &Templates,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) {
+    tracing::debug!("{} {}", request.method(), request.url());
+
+    let response = if matches!(request.method(), Method::Get | Method::Head) {
+        respond(request.url(), rev, ctx, templates, unpublished, conn).unwrap_or_else(|e| {
+            tracing::error!("Could not respond to {}: {e:?}", request.url());
+            internal_server_error()
+        })
+    } else {
+        Response::empty(405).boxed()
+    };
+
+    if let Err(e) = request.respond(response) {
+        tracing::warn!("Could not send response: {e}");
+    }
+}
+
+pub fn serve(
+    addr: SocketAddr,
+    rev: &Revision,
+    cache_dir: &Path,
+    hide_unpublished: bool,
+    conn: &mut DbConn,
+) -> anyhow::Result<()> {
+    let server = Server::http(addr).map_err(|e| anyhow::anyhow!(e))?;
+    let ctx = Context {
+        base_url: Url::parse(&format!("http://{addr}/"))?,
+        cache_dir,
+        body_html: None,
+        hide_unpublished,
+    };
+
+    tracing::info!("Serving revision {} at {}", rev.id, ctx.base_url);
+
+    let unpublished = ctx.unpublished(rev, conn)?;
+    let templates = publish::templates(rev, &ctx.base_url, &unpublished, conn)?;
+
+    for request in server.incoming_requests() {
+        handle(request, rev, &ctx, &templates, &unpublished, conn);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Site;
+
+    #[test]
+    fn append_html_to_body() {
+        let script = "<script></script>";
+        assert_eq!(
+            append_to_body(b"<html><body><p>Hi</p></body></html>\n", script).unwrap(),
+            b"<html><body><p>Hi</p><script></script></body></html>\n"
+        );
+        assert_eq!(
+            append_to_body(b"<p>Hi</p>", script).unwrap(),
+            b"<p>Hi</p><script></script>"
+        );
+    }
+
+    #[test]
+    fn redirect_non_ascii_path() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write(
+            "content/café/index.md",
+            "+++\ntitle = \"Café\"\naliases = [\"crème/\"]\n+++\nCafé\n",
+        );
+        let rev = site.create();
+        let ctx = Context {
+            base_url: Url::parse("http://localhost/").unwrap(),
+            cache_dir: &site.cache_dir(),
+            body_html: None,
+            hide_unpublished: false,
+        };
+        let templates =
+            publish::templates(&rev, &ctx.base_url, &Unpublished::new(), &mut site.conn()).unwrap();
+        let respond = |url: &str| {
+            respond(
+                url,
+                &rev,
+                &ctx,
+                &templates,
+                &Unpublished::new(),
+                &mut site.conn(),
+            )
+            .unwrap()
+        };
+
+        let location = |url: &str| {
+            let response = respond(url);
+            assert_eq!(response.status_code(), 301);
+            response
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Location"))
+                .unwrap()
+                .value
+                .to_string()
+        };
+        assert_eq!(location("/caf%C3%A9?q=1"), "/caf%C3%A9/");
+        // Aliases redirect to the page.
+        assert_eq!(location("/cr%C3%A8me/"), "/caf%C3%A9/index.html");
+
+        assert_eq!(respond("/caf%C3%A9/").status_code(), 200);
+        assert_eq!(respond("/caf%FF").status_code(), 400);
+        // Content source files are not served at their logical paths.
+        assert_eq!(respond("/caf%C3%A9/index.md").status_code(), 404);
+    }
+
+    #[test]
+    fn hide_unpublished_pages() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write(
+            "content/draft.md",
+            "+++\ntitle = \"Draft\"\ndraft = true\ntags = [\"Secret\"]\n+++\nDraft\n",
+        );
+        site.write("templates/tag.hbs", "{{tag.name}}");
+        site.write(
+            "content/scheduled.md",
+            "+++\ntitle = \"Scheduled\"\npublish_date = 2999-01-01T00:00:00Z\n+++\nLater\n",
+        );
+        site.write("content/index.md", "+++\ntitle = \"Home\"\n+++\nHome\n");
+        let rev = site.create();
+
+        for (hide_unpublished, status_code) in [(false, 200), (true, 404)] {
+            let ctx = Context {
+                base_url: Url::parse("http://localhost/").unwrap(),
+                cache_dir: &site.cache_dir(),
+                body_html: None,
+                hide_unpublished,
+            };
+            let unpublished = ctx.unpublished(&rev, &mut site.conn()).unwrap();
+            let templates =
+                publish::templates(&rev, &ctx.base_url, &unpublished, &mut site.conn()).unwrap();
+            for url in ["/draft.html", "/scheduled.html", "/tags/secret/"] {
+                let response =
+                    respond(url, &rev, &ctx, &templates, &unpublished, &mut site.conn()).unwrap();
+                assert_eq!(response.status_code(), status_code, "{url}");
+            }
+        }
+    }
+}
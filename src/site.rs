@@ -0,0 +1,211 @@
+//! Site configuration.
+//!
+//! `site.toml` in the source directory is read when a revision is created and
+//! stored with the revision so older revisions are published with the
+//! configuration they were created with.
+//!
+//! ```toml
+//! base_url = "https://example.com/"
+//! title = "Example"
+//! description = "An example site"
+//! author = "Example Author"
+//! language = "en"
+//! # Template for pages without a template or section template.
+//! template = "page.hbs"
+//! # How page aliases are published: "html" or "file".
+//! redirects = "file"
+//!
+//! # Markdown extensions. See `crate::markdown`.
+//! [markdown]
+//! tables = true
+//!
+//! # Syntax highlighting. See `crate::highlight`.
+//! [highlight]
+//! theme = "InspiredGitHub"
+//!
+//! # Tag page templates. See `crate::taxonomy`.
+//! [taxonomy]
+//! index_template = "tags.hbs"
+//! term_template = "tag.hbs"
+//!
+//! # Routes of content pages. See `Routes`.
+//! [routes]
+//! pretty = true
+//!
+//! [extra]
+//! # Any other data for templates.
+//! ```
+//!
+//! Every key is optional. `base_url` and `redirects` are used if the publish
+//! options do not set them.
+
+use std::{fs, io, path::Path};
+
+use anyhow::Context;
+use serde_derive::Deserialize;
+use serde_json::{Map, Value};
+use url::Url;
+
+use crate::{content, highlight, markdown, models::revision::Revision, redirect, taxonomy};
+
+/// Name of the configuration file in the source directory.
+pub const FILE_NAME: &str = "site.toml";
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub base_url: Option<Url>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub template: Option<String>,
+    pub redirects: Option<redirect::Style>,
+    #[serde(default)]
+    pub markdown: markdown::Extensions,
+    pub highlight: Option<highlight::Config>,
+    #[serde(default)]
+    pub taxonomy: taxonomy::Config,
+    #[serde(default)]
+    pub routes: Routes,
+    #[serde(default)]
+    pub extra: Map<String, Value>,
+}
+
+impl Config {
+    /// Parses and validates the configuration.
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        let config: Self =
+            serde_json::from_value(Value::Object(content::front_matter_json(contents)?))?;
+
+        if let Some(base_url) = &config.base_url {
+            anyhow::ensure!(
+                matches!(base_url.scheme(), "http" | "https"),
+                "base_url must be an http or https URL"
+            );
+            anyhow::ensure!(base_url.path().ends_with('/'), "base_url must end with a /");
+        }
+
+        if let Some(highlight) = &config.highlight {
+            highlight.validate()?;
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the configuration the revision was created with.
+    pub fn load(rev: &Revision) -> anyhow::Result<Self> {
+        rev.site_config
+            .as_deref()
+            .map_or_else(|| Ok(Self::default()), Self::parse)
+            .with_context(|| format!("invalid {FILE_NAME} in revision {}", rev.id))
+    }
+}
+
+/// Route rules for content pages.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Routes {
+    /// Publishes `content/about.md` at `about/index.html` instead of
+    /// `about.html`. Index pages are published at `index.html` either way.
+    pub pretty: bool,
+}
+
+impl Routes {
+    /// Returns the route of a content file by its path relative to `content/`.
+    ///
+    /// Returns `None` if the file is not Markdown.
+    pub fn content(&self, path: &str) -> Option<String> {
+        let path = path.strip_suffix(".md")?;
+        let is_index = path
+            .rsplit('/')
+            .next()
+            .is_some_and(|stem| stem == "index" || stem == "_index");
+        if self.pretty && !is_index {
+            Some(format!("{path}/index.html"))
+        } else {
+            Some(format!("{path}.html"))
+        }
+    }
+}
+
+/// Reads and validates the configuration file in the source directory.
+///
+/// Returns the contents to store with a revision or `None` if there is no file.
+pub fn read(src: &Path) -> anyhow::Result<Option<String>> {
+    let path = src.join(FILE_NAME);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Config::parse(&contents).with_context(|| format!("invalid {}", path.display()))?;
+
+    Ok(Some(contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::parse(
+            r#"
+base_url = "https://example.com/blog/"
+title = "Example"
+redirects = "file"
+
+[extra]
+twitter = "example"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.base_url,
+            Some(Url::parse("https://example.com/blog/").unwrap())
+        );
+        assert_eq!(config.title.as_deref(), Some("Example"));
+        assert_eq!(config.redirects, Some(redirect::Style::File));
+        assert_eq!(config.extra["twitter"], "example");
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert!(Config::parse("titel = \"Example\"").is_err());
+        assert!(Config::parse("title = 1").is_err());
+        assert!(Config::parse("base_url = \"https://example.com/blog\"").is_err());
+        assert!(Config::parse("base_url = \"file:///tmp/\"").is_err());
+        assert!(Config::parse("redirects = \"meta\"").is_err());
+        assert!(Config::parse("[routes]\npretty = \"yes\"").is_err());
+    }
+
+    #[test]
+    fn content_routes() {
+        let routes = Routes::default();
+        assert_eq!(routes.content("about.md").as_deref(), Some("about.html"));
+        assert_eq!(
+            routes.content("blog/index.md").as_deref(),
+            Some("blog/index.html")
+        );
+        assert_eq!(routes.content("notes.txt"), None);
+
+        let routes = Config::parse("[routes]\npretty = true").unwrap().routes;
+        assert_eq!(
+            routes.content("about.md").as_deref(),
+            Some("about/index.html")
+        );
+        assert_eq!(
+            routes.content("blog/a.md").as_deref(),
+            Some("blog/a/index.html")
+        );
+        assert_eq!(routes.content("index.md").as_deref(), Some("index.html"));
+        assert_eq!(
+            routes.content("blog/_index.md").as_deref(),
+            Some("blog/_index.html")
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Publishes revisions into staging directories and atomically switches between them.
+//!
+//! The build directory contains a directory per published revision and a
+//! `current` symlink pointing to the live directory:
+//!
+//! ```text
+//! build/
+//!   current -> revision-2-1681000100000000000
+//!   revision-1-1681000000000000000/
+//!   revision-2-1681000100000000000/
+//! ```
+//!
+//! Directories are named with the revision and the time in nanoseconds. A web
+//! server should serve the `current` symlink. Rolling back is re-pointing the
+//! symlink to an older directory which is still kept.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::models::revision::Revision;
+
+/// Name of the symlink to the live directory.
+pub const CURRENT: &str = "current";
+
+const PREFIX: &str = "revision-";
+
+/// Creates a new empty staging directory for the revision.
+pub fn create_dir(build_dir: &Path, rev: &Revision) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(build_dir)?;
+
+    let mut now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    loop {
+        let staging_dir = build_dir.join(format!("{PREFIX}{}-{now}", rev.id));
+        match fs::create_dir(&staging_dir) {
+            Ok(()) => return Ok(staging_dir),
+            // Another publish used the same time.
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => now += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn symlink_dir(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(original, link)
+}
+
+/// Returns the directory the `current` symlink points to.
+pub fn current(build_dir: &Path) -> io::Result<Option<PathBuf>> {
+    match fs::read_link(build_dir.join(CURRENT)) {
+        Ok(target) => Ok(Some(build_dir.join(target))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Atomically points the `current` symlink to the staging directory.
+pub fn activate(build_dir: &Path, staging_dir: &Path) -> io::Result<()> {
+    let target = staging_dir
+        .file_name()
+        .ok_or_else(|| io::Error::other("staging directory has no name"))?;
+
+    let tmp_link = build_dir.join(format!("{CURRENT}.tmp"));
+    if fs::symlink_metadata(&tmp_link).is_ok() {
+        fs::remove_file(&tmp_link)?;
+    }
+    symlink_dir(Path::new(target), &tmp_link)?;
+
+    let link = build_dir.join(CURRENT);
+    #[cfg(windows)]
+    if fs::symlink_metadata(&link).is_ok() {
+        // Windows cannot rename over an existing directory symlink.
+        fs::remove_dir(&link)?;
+    }
+    fs::rename(tmp_link, link)?;
+
+    Ok(())
+}
+
+/// Points the `current` symlink to a previously published directory.
+///
+/// Returns the directory or `None` if it was already removed.
+pub fn activate_existing(build_dir: &Path, name: &str) -> io::Result<Option<PathBuf>> {
+    let staging_dir = build_dir.join(name);
+    if !name.starts_with(PREFIX) || !staging_dir.is_dir() {
+        return Ok(None);
+    }
+    activate(build_dir, &staging_dir)?;
+    Ok(Some(staging_dir))
+}
+
+/// Published directories ordered from oldest to newest.
+fn published_dirs(build_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(build_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let file_name = entry.file_name();
+        let Some(timestamp) = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(PREFIX))
+            .and_then(|name| name.rsplit_once('-'))
+            .and_then(|(_, timestamp)| timestamp.parse::<u128>().ok())
+        else {
+            continue;
+        };
+        dirs.push((timestamp, entry.path()));
+    }
+    dirs.sort();
+    Ok(dirs.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Removes all but the `keep` most recently published directories.
+///
+/// The directory the `current` symlink points to is never removed.
+pub fn remove_old(build_dir: &Path, keep: usize) -> io::Result<Vec<PathBuf>> {
+    let current = current(build_dir)?;
+
+    let mut dirs = published_dirs(build_dir)?;
+    let old_len = dirs.len().saturating_sub(keep);
+    dirs.truncate(old_len);
+    dirs.retain(|dir| Some(dir) != current.as_ref());
+
+    for dir in &dirs {
+        tracing::trace!("Removing published directory: {}", dir.display());
+        fs::remove_dir_all(dir)?;
+    }
+
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    fn revision(id: i64) -> Revision {
+        Revision {
+            id,
+            created_at: NaiveDateTime::default(),
+            site_config: None,
+        }
+    }
+
+    #[test]
+    fn publish_and_roll_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path();
+
+        // Publishing the same revision twice does not reuse the directory.
+        let first = create_dir(build_dir, &revision(1)).unwrap();
+        let second = create_dir(build_dir, &revision(1)).unwrap();
+        let third = create_dir(build_dir, &revision(2)).unwrap();
+        assert_ne!(first, second);
+
+        activate(build_dir, &third).unwrap();
+        assert_eq!(current(build_dir).unwrap(), Some(third.clone()));
+
+        assert_eq!(remove_old(build_dir, 2).unwrap(), vec![first.clone()]);
+
+        let name = second.file_name().unwrap().to_str().unwrap();
+        assert_eq!(
+            activate_existing(build_dir, name).unwrap(),
+            Some(second.clone())
+        );
+        assert_eq!(current(build_dir).unwrap(), Some(second.clone()));
+
+        let name = first.file_name().unwrap().to_str().unwrap();
+        assert_eq!(activate_existing(build_dir, name).unwrap(), None);
+        assert_eq!(activate_existing(build_dir, CURRENT).unwrap(), None);
+    }
+}
@@ -0,0 +1,343 @@
+//! Tag index and term pages.
+//!
+//! Pages are grouped by the `tags` in their front matter. The tag index lists
+//! every tag and each term page lists the pages with the tag. Both are routes
+//! of the revision which are rendered with a template.
+//!
+//! The tag index template is rendered with `tags`, a list of tags with their
+//! `name`, `slug`, `count`, `route` and `url`. A term page template is rendered
+//! with the `tag` and its `pages`, a list of pages with their `title`, `date`,
+//! `description`, `summary`, `route` and `url` with the newest page first.
+//!
+//! The templates are `tags.hbs` and `tag.hbs` unless the `[taxonomy]` table
+//! of the site configuration sets `index_template` or `term_template`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use diesel::prelude::*;
+use serde_derive::Deserialize;
+use serde_json::{json, Map, Value};
+use url::Url;
+
+use crate::{
+    models::{
+        page::Page, page_tag::PageTag, revision::Revision, route::NewRoute, route::Route, DbConn,
+    },
+    visibility::Unpublished,
+};
+
+/// Default template used to render the tag index.
+pub const INDEX_TEMPLATE: &str = "tags.hbs";
+
+/// Default template used to render a tag's term page.
+pub const TERM_TEMPLATE: &str = "tag.hbs";
+
+/// Taxonomy options of the site configuration.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub index_template: String,
+    pub term_template: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            index_template: INDEX_TEMPLATE.to_string(),
+            term_template: TERM_TEMPLATE.to_string(),
+        }
+    }
+}
+
+const PREFIX: &str = "tags/";
+
+const INDEX_ROUTE: &str = "tags/index.html";
+
+/// Returns the URL path segment for a tag.
+///
+/// Letters are lowercased and runs of other characters are replaced by a single `-`.
+pub fn slug(tag: &str) -> String {
+    let mut slug = String::new();
+    for c in tag.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+
+    slug
+}
+
+fn term_route(slug: &str) -> String {
+    format!("{PREFIX}{slug}/index.html")
+}
+
+/// A generated taxonomy page.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Kind<'a> {
+    Index,
+    /// Term page for the tags with the slug.
+    Term(&'a str),
+}
+
+/// Returns the kind of taxonomy page for a route if it is one.
+pub fn kind(route: &str) -> Option<Kind<'_>> {
+    if route == INDEX_ROUTE {
+        return Some(Kind::Index);
+    }
+
+    route
+        .strip_prefix(PREFIX)
+        .and_then(|route| route.strip_suffix("/index.html"))
+        .filter(|slug| !slug.is_empty() && !slug.contains('/'))
+        .map(Kind::Term)
+}
+
+/// Returns the tags of published pages in the revision grouped by slug.
+fn tags_by_slug(
+    rev: &Revision,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> QueryResult<BTreeMap<String, BTreeMap<String, Vec<String>>>> {
+    let mut tags = BTreeMap::<String, BTreeMap<String, Vec<String>>>::new();
+    for page_tag in PageTag::with_revision(rev, conn)?
+        .into_iter()
+        .filter(|page_tag| !unpublished.contains_key(&page_tag.input_file_id))
+    {
+        let slug = slug(&page_tag.tag);
+        if slug.is_empty() {
+            tracing::warn!(
+                "In revision {} tag {:?} of {} has no characters usable in a URL",
+                rev.id,
+                page_tag.tag,
+                page_tag.input_file_id
+            );
+            continue;
+        }
+
+        tags.entry(slug)
+            .or_default()
+            .entry(page_tag.tag)
+            .or_default()
+            .push(page_tag.input_file_id);
+    }
+
+    Ok(tags)
+}
+
+/// Adds the tag index and term routes to the revision.
+///
+/// `templates` is a map of template names to input file IDs. Routes are only
+/// added if their template exists and no other route has the same path.
+pub fn create_routes(
+    rev: &Revision,
+    config: &Config,
+    templates: &BTreeMap<String, String>,
+    conn: &mut DbConn,
+) -> anyhow::Result<()> {
+    let tags = tags_by_slug(rev, &Unpublished::new(), conn)?;
+    if tags.is_empty() {
+        return Ok(());
+    }
+
+    let create = |route: &str, template: &str, conn: &mut DbConn| -> anyhow::Result<()> {
+        let Some(input_file_id) = templates.get(template) else {
+            tracing::warn!(
+                "In revision {} skipping route {route} without template {template}",
+                rev.id
+            );
+            return Ok(());
+        };
+
+        if Route::by_revision_id_and_route(rev.id, route)
+            .first(conn)
+            .optional()?
+            .is_some()
+        {
+            tracing::warn!("In revision {} route {route} already exists", rev.id);
+            return Ok(());
+        }
+
+        tracing::trace!("Adding taxonomy route: {}", route);
+        NewRoute::new(rev.id, route, input_file_id).create(conn)?;
+        Ok(())
+    };
+
+    create(INDEX_ROUTE, &config.index_template, conn)?;
+    for slug in tags.keys() {
+        create(&term_route(slug), &config.term_template, conn)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the term routes of tags which are only used by `unpublished` pages.
+///
+/// Term routes are created for every tag in the revision so they are not
+/// published if none of the tag's pages are.
+pub fn unpublished_routes(
+    rev: &Revision,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> QueryResult<BTreeSet<String>> {
+    if unpublished.is_empty() {
+        return Ok(BTreeSet::new());
+    }
+
+    let published = tags_by_slug(rev, unpublished, conn)?;
+    Ok(tags_by_slug(rev, &Unpublished::new(), conn)?
+        .into_keys()
+        .filter(|slug| !published.contains_key(slug))
+        .map(|slug| term_route(&slug))
+        .collect())
+}
+
+/// Returns the inputs which affect the taxonomy pages.
+///
+/// Page input file IDs change whenever the page's front matter changes.
+pub fn dependencies(rev: &Revision, conn: &mut DbConn) -> QueryResult<Vec<String>> {
+    Ok(PageTag::with_revision(rev, conn)?
+        .into_iter()
+        .map(|page_tag| format!("{}\n{}", page_tag.tag, page_tag.input_file_id))
+        .collect())
+}
+
+fn page_context(
+    page: &Page,
+    base_url: &Url,
+    rev: &Revision,
+    conn: &mut DbConn,
+) -> anyhow::Result<Option<Value>> {
+    let Some(route) = Route::by_revision_id_and_input_file_id(rev.id, &page.input_file_id)
+        .first(conn)
+        .optional()?
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(json!({
+        "title": page.title,
+        "date": page.date,
+        "description": page.description,
+        "summary": page.summary,
+        "route": route.route,
+        "url": base_url.join(&route.route)?.as_str(),
+    })))
+}
+
+/// Returns the template data for a taxonomy page.
+///
+/// `unpublished` pages are not listed.
+pub fn context(
+    kind: &Kind<'_>,
+    rev: &Revision,
+    base_url: &Url,
+    unpublished: &Unpublished,
+    conn: &mut DbConn,
+) -> anyhow::Result<Map<String, Value>> {
+    let tags = tags_by_slug(rev, unpublished, conn)?;
+
+    let tag_context =
+        |slug: &str, names: &BTreeMap<String, Vec<String>>| -> anyhow::Result<Value> {
+            let route = term_route(slug);
+            Ok(json!({
+                "name": names.keys().next(),
+                "slug": slug,
+                "count": names.values().map(Vec::len).sum::<usize>(),
+                "url": base_url.join(&route)?.as_str(),
+                "route": route,
+            }))
+        };
+
+    match kind {
+        Kind::Index => {
+            let tags = tags
+                .iter()
+                .map(|(slug, names)| tag_context(slug, names))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Ok(Map::from_iter([("tags".to_string(), Value::Array(tags))]))
+        }
+        Kind::Term(slug) => {
+            // Every page with the tag may be unpublished.
+            let no_names = BTreeMap::new();
+            let names = tags.get(*slug).unwrap_or(&no_names);
+
+            let mut pages = BTreeMap::new();
+            for name in names.keys() {
+                for page in PageTag::pages(rev, name, conn)? {
+                    if !unpublished.contains_key(&page.input_file_id) {
+                        pages.insert(page.input_file_id.clone(), page);
+                    }
+                }
+            }
+            let mut pages = pages.into_values().collect::<Vec<_>>();
+            pages.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.title.cmp(&b.title)));
+
+            let pages = pages
+                .iter()
+                .map(|page| page_context(page, base_url, rev, conn))
+                .filter_map(Result::transpose)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            Ok(Map::from_iter([
+                ("tag".to_string(), tag_context(slug, names)?),
+                ("pages".to_string(), Value::Array(pages)),
+            ]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Site;
+
+    #[test]
+    fn slug_of_tags() {
+        assert_eq!(slug("Rust"), "rust");
+        assert_eq!(slug("Static Site Generators"), "static-site-generators");
+        assert_eq!(slug("  C++ & WebAssembly!  "), "c-webassembly");
+        assert_eq!(slug("日本語"), "日本語");
+        assert_eq!(slug("--"), "");
+    }
+
+    #[test]
+    fn kind_of_routes() {
+        assert_eq!(kind("tags/index.html"), Some(Kind::Index));
+        assert_eq!(kind("tags/rust/index.html"), Some(Kind::Term("rust")));
+        assert_eq!(kind("tags/rust/other.html"), None);
+        assert_eq!(kind("tags/a/b/index.html"), None);
+        assert_eq!(kind("index.html"), None);
+    }
+
+    #[test]
+    fn templates_from_site_config() {
+        let site = Site::new();
+        site.write("site.toml", "[taxonomy]\nterm_template = \"term.hbs\"\n");
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write("templates/tag.hbs", "{{tag.name}}");
+        site.write("templates/term.hbs", "{{tag.name}}");
+        site.write(
+            "content/post.md",
+            "+++\ntitle = \"Post\"\ntags = [\"Rust\"]\n+++\nPost\n",
+        );
+        let rev = site.create();
+        let mut conn = site.conn();
+
+        let route = Route::by_revision_id_and_route(rev.id, "tags/rust/index.html")
+            .first(&mut conn)
+            .unwrap();
+        assert!(route.input_file_id.ends_with("templates/term.hbs"));
+        // The index template does not exist.
+        assert!(Route::by_revision_id_and_route(rev.id, INDEX_ROUTE)
+            .first(&mut conn)
+            .optional()
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,223 @@
+//! Selects the template for content pages.
+//!
+//! A content page is rendered with the first template found from:
+//!
+//! 1. `template` in the page's front matter
+//! 2. `_template` in the front matter of the `_index.md` in the page's
+//!    directory or the nearest parent directory under `content/`
+//! 3. `template` in the site configuration
+//! 4. `templates/default.hbs`
+
+use std::{collections::BTreeMap, path::Path};
+
+use toml_edit::Document;
+
+use crate::{
+    models::{page::Page, revision::Revision, DbConn},
+    publish::Templates,
+    site,
+};
+
+/// Template used by pages without a `template` or section template.
+pub const DEFAULT: &str = "default.hbs";
+
+/// Front matter key in a section's `_index.md` with the template for pages in the section.
+pub const SECTION_KEY: &str = "_template";
+
+/// Returns the logical paths of the section indexes which apply to a content
+/// file with the nearest first.
+fn section_indexes(logical_path: &str) -> Vec<String> {
+    Path::new(logical_path)
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with("content"))
+        .map(|dir| dir.join("_index.md").to_string_lossy().to_string())
+        .collect()
+}
+
+/// Returns the section templates in the revision keyed by the logical path of
+/// the section's `_index.md`.
+pub fn section_templates(
+    rev: &Revision,
+    conn: &mut DbConn,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut templates = BTreeMap::new();
+    for (logical_path, page) in Page::section_indexes(rev, conn)? {
+        let Some(front_matter) = page.front_matter else {
+            continue;
+        };
+        let doc = front_matter.parse::<Document>()?;
+        let Some(item) = doc.get(SECTION_KEY) else {
+            continue;
+        };
+        let Some(template) = item.as_str() else {
+            anyhow::bail!("{SECTION_KEY} in {logical_path} must be a string");
+        };
+        templates.insert(logical_path, template.to_string());
+    }
+    Ok(templates)
+}
+
+/// Returns the name of the template for a content page.
+///
+/// Section templates and the site configuration are the ones `templates` was
+/// created with.
+pub fn resolve(logical_path: &str, page: &Page, templates: &Templates) -> anyhow::Result<String> {
+    if let Some(template) = &page.template {
+        return Ok(template.clone());
+    }
+
+    if let Some(template) = section_indexes(logical_path)
+        .iter()
+        .find_map(|index| templates.section_template(index))
+    {
+        return Ok(template.to_string());
+    }
+
+    if let Some(template) = &templates.config().template {
+        return Ok(template.clone());
+    }
+
+    if templates.has_template(DEFAULT) {
+        return Ok(DEFAULT.to_string());
+    }
+
+    anyhow::bail!(
+        "{logical_path} has no template: set template in its front matter, \
+        {SECTION_KEY} in a section's _index.md, template in {}, or add templates/{DEFAULT}",
+        site::FILE_NAME
+    )
+}
+
+/// Returns the inputs which affect the templates of content pages.
+pub fn dependencies(rev: &Revision, conn: &mut DbConn) -> anyhow::Result<Vec<String>> {
+    Ok(section_templates(rev, conn)?
+        .into_iter()
+        .map(|(logical_path, template)| format!("{logical_path}\n{template}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use diesel::prelude::*;
+    use url::Url;
+
+    use super::*;
+    use crate::{
+        models::{input_file::InputFile, route::Route},
+        publish,
+        testing::Site,
+        visibility::Unpublished,
+    };
+
+    #[test]
+    fn section_indexes_nearest_first() {
+        assert_eq!(
+            section_indexes("content/blog/2024/post.md"),
+            vec![
+                "content/blog/2024/_index.md",
+                "content/blog/_index.md",
+                "content/_index.md",
+            ]
+        );
+        assert_eq!(
+            section_indexes("content/about.md"),
+            vec!["content/_index.md"]
+        );
+    }
+
+    /// Returns the logical path and page of a content route.
+    fn page(rev: &Revision, route: &str, conn: &mut DbConn) -> (String, Page) {
+        let route = Route::by_revision_id_and_route(rev.id, route)
+            .get_result(conn)
+            .unwrap();
+        let input_file = InputFile::by_id(&route.input_file_id)
+            .get_result(conn)
+            .unwrap();
+        let page = Page::by_input_file_id(&input_file.id)
+            .get_result(conn)
+            .unwrap();
+        (input_file.logical_path, page)
+    }
+
+    #[test]
+    fn section_template_precedence() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write(
+            "content/_index.md",
+            "+++\n_template = \"root.hbs\"\n+++\nHome\n",
+        );
+        site.write(
+            "content/blog/_index.md",
+            "+++\n_template = \"blog.hbs\"\n+++\nBlog\n",
+        );
+        // A section without a template inherits the parent section's template.
+        site.write(
+            "content/blog/2024/_index.md",
+            "+++\ntitle = \"2024\"\n+++\n2024\n",
+        );
+        site.write("content/blog/2024/a.md", "A\n");
+        site.write(
+            "content/blog/2024/b.md",
+            "+++\ntemplate = \"post.hbs\"\n+++\nB\n",
+        );
+        site.write("content/about.md", "About\n");
+        site.write(
+            "content/docs/_index.md",
+            "+++\n_template = \"docs.hbs\"\n+++\nDocs\n",
+        );
+        site.write("content/docs/guide/intro.md", "Intro\n");
+        let rev = site.create();
+        let mut conn = site.conn();
+
+        let templates = publish::templates(
+            &rev,
+            &Url::parse("https://example.com/").unwrap(),
+            &Unpublished::new(),
+            &mut conn,
+        )
+        .unwrap();
+        let mut resolve = |route: &str| {
+            let (logical_path, page) = page(&rev, route, &mut conn);
+            resolve(&logical_path, &page, &templates).unwrap()
+        };
+
+        assert_eq!(resolve("blog/2024/a.html"), "blog.hbs");
+        assert_eq!(resolve("blog/2024/b.html"), "post.hbs");
+        assert_eq!(resolve("about.html"), "root.hbs");
+        assert_eq!(resolve("docs/guide/intro.html"), "docs.hbs");
+    }
+
+    #[test]
+    fn site_and_default_templates() {
+        let site = Site::new();
+        site.write("templates/default.hbs", "{{{content}}}");
+        site.write("content/index.md", "Home\n");
+        let rev = site.create();
+        site.write("site.toml", "template = \"page.hbs\"\n");
+        let with_config = site.create();
+        fs::remove_file(site.src().join("templates/default.hbs")).unwrap();
+        fs::remove_file(site.src().join("site.toml")).unwrap();
+        let without_templates = site.create();
+        let mut conn = site.conn();
+
+        let mut resolve = |rev: &Revision| {
+            let templates = publish::templates(
+                rev,
+                &Url::parse("https://example.com/").unwrap(),
+                &Unpublished::new(),
+                &mut conn,
+            )
+            .unwrap();
+            let (logical_path, page) = page(rev, "index.html", &mut conn);
+            resolve(&logical_path, &page, &templates)
+        };
+
+        assert_eq!(resolve(&rev).unwrap(), DEFAULT);
+        assert_eq!(resolve(&with_config).unwrap(), "page.hbs");
+        assert!(resolve(&without_templates).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Helpers for tests which create revisions from a site on disk.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use tempfile::TempDir;
+
+use crate::{
+    build,
+    models::{self, revision::Revision, DbConn, DbPool},
+};
+
+/// A source directory, cache directory and database in a temporary directory.
+pub struct Site {
+    dir: TempDir,
+    pub pool: DbPool,
+}
+
+impl Site {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        for src_dir in ["assets", "content", "static", "templates"] {
+            fs::create_dir_all(dir.path().join("src").join(src_dir)).unwrap();
+        }
+        fs::create_dir(dir.path().join("cache")).unwrap();
+
+        let pool = models::establish_connection_pool(dir.path().join("site.db").to_str().unwrap())
+            .unwrap();
+        models::run_migrations(&mut *pool.get().unwrap()).unwrap();
+
+        Self { dir, pool }
+    }
+
+    /// Temporary directory for other files such as build directories.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn src(&self) -> PathBuf {
+        self.dir.path().join("src")
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        self.dir.path().join("cache")
+    }
+
+    pub fn conn(&self) -> PooledConnection<ConnectionManager<DbConn>> {
+        self.pool.get().unwrap()
+    }
+
+    /// Writes a file in the source directory.
+    pub fn write(&self, logical_path: &str, contents: &str) {
+        let path = self.src().join(logical_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Creates a revision from the source directory.
+    pub fn create(&self) -> Revision {
+        in_thread_pool(|| build::create(&self.src(), &self.cache_dir(), false, &self.pool)).unwrap()
+    }
+}
+
+/// Runs `f` in its own thread pool.
+///
+/// Walking the source directory blocks on channels between rayon tasks and
+/// needs more than one thread.
+pub fn in_thread_pool<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap()
+        .install(f)
+}
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `f` on the current thread and returns the warnings it logged, one per line.
+pub fn warnings<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_max_level(tracing::Level::WARN)
+        .with_ansi(false)
+        .without_time()
+        .with_target(false)
+        .with_level(false)
+        .finish();
+
+    let result = tracing::subscriber::with_default(subscriber, f);
+    let warnings = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    (result, warnings)
+}
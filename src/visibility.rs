@@ -0,0 +1,226 @@
+//! Decides which pages are published.
+//!
+//! Drafts and pages outside of their publish and expiry dates are kept in the
+//! revision but are not published.
+
+use std::{collections::BTreeMap, fmt};
+
+use chrono::NaiveDateTime;
+use diesel::QueryResult;
+
+use crate::models::{page::Page, revision::Revision, DbConn};
+
+/// Why a page is not published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Draft,
+    /// The publish date is in the future.
+    Scheduled,
+    /// The expiry date has passed.
+    Expired,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Draft => f.write_str("draft"),
+            Reason::Scheduled => f.write_str("scheduled"),
+            Reason::Expired => f.write_str("expired"),
+        }
+    }
+}
+
+/// Map of unpublished page input file IDs to the reason they are not published.
+pub type Unpublished = BTreeMap<String, Reason>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Visibility {
+    pub include_drafts: bool,
+    /// Time in UTC to compare publish and expiry dates against.
+    pub now: NaiveDateTime,
+}
+
+impl Visibility {
+    /// Returns why the page is not published or `None` if it is published.
+    pub fn reason(&self, page: &Page) -> Option<Reason> {
+        if page.draft && !self.include_drafts {
+            Some(Reason::Draft)
+        } else if page.publish_date.is_some_and(|date| date > self.now) {
+            Some(Reason::Scheduled)
+        } else if page.expiry_date.is_some_and(|date| date <= self.now) {
+            Some(Reason::Expired)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the pages in the revision which are not published.
+    pub fn unpublished(&self, rev: &Revision, conn: &mut DbConn) -> QueryResult<Unpublished> {
+        Ok(Page::with_revision(rev, conn)?
+            .into_iter()
+            .filter_map(|page| {
+                self.reason(&page)
+                    .map(|reason| (page.input_file_id, reason))
+            })
+            .collect())
+    }
+}
+
+/// A page which is published or unpublished within a window of time.
+#[derive(Debug)]
+pub struct Due {
+    pub input_file_id: String,
+    /// True if the page becomes published and false if it becomes unpublished.
+    pub is_published: bool,
+    /// The publish or expiry date which was crossed.
+    pub at: Option<NaiveDateTime>,
+}
+
+impl Visibility {
+    /// Returns the pages in the revision which are published at `since` but
+    /// not at `self.now` or the reverse.
+    pub fn due(
+        &self,
+        rev: &Revision,
+        since: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> QueryResult<Vec<Due>> {
+        let before = Visibility {
+            now: since,
+            ..*self
+        };
+
+        Ok(Page::with_revision(rev, conn)?
+            .into_iter()
+            .filter_map(|page| {
+                let was_published = before.reason(&page).is_none();
+                let is_published = self.reason(&page).is_none();
+                (was_published != is_published).then_some(Due {
+                    at: if is_published {
+                        page.publish_date
+                    } else {
+                        page.expiry_date
+                    },
+                    input_file_id: page.input_file_id,
+                    is_published,
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::testing::Site;
+
+    fn at(year: i32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    fn page(draft: bool, publish_date: Option<i32>, expiry_date: Option<i32>) -> Page {
+        Page {
+            input_file_id: "id,content/page.md".to_string(),
+            front_matter: None,
+            offset: 0,
+            date: None,
+            description: None,
+            excerpt: None,
+            draft,
+            expiry_date: expiry_date.map(at),
+            keywords: None,
+            template: None,
+            publish_date: publish_date.map(at),
+            summary: None,
+            title: None,
+        }
+    }
+
+    #[test]
+    fn reasons() {
+        let visibility = Visibility {
+            include_drafts: false,
+            now: at(2020),
+        };
+        assert_eq!(visibility.reason(&page(false, None, None)), None);
+        assert_eq!(
+            visibility.reason(&page(true, None, None)),
+            Some(Reason::Draft)
+        );
+        assert_eq!(
+            visibility.reason(&page(false, Some(2021), None)),
+            Some(Reason::Scheduled)
+        );
+        assert_eq!(visibility.reason(&page(false, Some(2020), None)), None);
+        assert_eq!(
+            visibility.reason(&page(false, None, Some(2020))),
+            Some(Reason::Expired)
+        );
+        assert_eq!(
+            visibility.reason(&page(false, Some(2019), Some(2021))),
+            None
+        );
+
+        let visibility = Visibility {
+            include_drafts: true,
+            ..visibility
+        };
+        assert_eq!(visibility.reason(&page(true, None, None)), None);
+        // Drafts are still scheduled.
+        assert_eq!(
+            visibility.reason(&page(true, Some(2021), None)),
+            Some(Reason::Scheduled)
+        );
+    }
+
+    #[test]
+    fn due_in_window() {
+        let site = Site::new();
+        site.write(
+            "content/scheduled.md",
+            "+++\ntitle = \"Scheduled\"\npublish_date = 2021-01-01T00:00:00Z\n+++\nA\n",
+        );
+        site.write(
+            "content/expiring.md",
+            "+++\ntitle = \"Expiring\"\nexpiry_date = 2022-01-01T00:00:00Z\n+++\nB\n",
+        );
+        site.write("content/index.md", "+++\ntitle = \"Home\"\n+++\nC\n");
+        let rev = site.create();
+
+        let due = |since: i32, now: i32| {
+            let visibility = Visibility {
+                include_drafts: false,
+                now: at(now),
+            };
+            let mut due = visibility
+                .due(&rev, at(since), &mut site.conn())
+                .unwrap()
+                .into_iter()
+                .map(|due| (due.input_file_id, due.is_published, due.at))
+                .collect::<Vec<_>>();
+            due.sort();
+            due
+        };
+
+        assert!(due(2019, 2020).is_empty());
+        let published = due(2020, 2021);
+        assert_eq!(published.len(), 1);
+        assert!(published[0].0.ends_with("content/scheduled.md"));
+        assert!(published[0].1);
+        assert_eq!(published[0].2, Some(at(2021)));
+
+        let expired = due(2021, 2023);
+        assert_eq!(expired.len(), 1);
+        assert!(expired[0].0.ends_with("content/expiring.md"));
+        assert!(!expired[0].1);
+        assert_eq!(expired[0].2, Some(at(2022)));
+
+        assert_eq!(due(2020, 2023).len(), 2);
+        assert!(due(2023, 2024).is_empty());
+    }
+}